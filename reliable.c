@@ -56,6 +56,7 @@ static void default_assert_handler( RELIABLE_CONST char * condition, RELIABLE_CO
 
 static int log_level = 0;
 static int (*printf_function)( RELIABLE_CONST char *, ... ) = ( int (*)( RELIABLE_CONST char *, ... ) ) printf;
+static void (*log_function)( int, RELIABLE_CONST char * ) = NULL;
 void (*reliable_assert_function)( RELIABLE_CONST char *, RELIABLE_CONST char *, RELIABLE_CONST char * file, int line ) = default_assert_handler;
 
 void reliable_log_level( int level )
@@ -69,6 +70,11 @@ void reliable_set_printf_function( int (*function)( RELIABLE_CONST char *, ... )
     printf_function = function;
 }
 
+void reliable_set_log_function( void (*function)( int level, RELIABLE_CONST char * message ) )
+{
+    log_function = function;
+}
+
 void reliable_set_assert_function( void (*function)( RELIABLE_CONST char *, RELIABLE_CONST char *, RELIABLE_CONST char * file, int line ) )
 {
     reliable_assert_function = function;
@@ -84,7 +90,10 @@ void reliable_printf( int level, RELIABLE_CONST char * format, ... )
     va_start( args, format );
     char buffer[4*1024];
     vsprintf( buffer, format, args );
-    printf_function( "%s", buffer );
+    if ( log_function )
+        log_function( level, buffer );
+    else
+        printf_function( "%s", buffer );
     va_end( args );
 }
 
@@ -137,7 +137,9 @@ void reliable_log_level( int level );
 
 void reliable_set_printf_function( int (*function)( RELIABLE_CONST char *, ... ) );
 
-extern void (*netcode_assert_function)( RELIABLE_CONST char *, RELIABLE_CONST char *, RELIABLE_CONST char * file, int line );
+void reliable_set_log_function( void (*function)( int /*level*/, RELIABLE_CONST char * /*message*/ ) );
+
+extern void (*reliable_assert_function)( RELIABLE_CONST char *, RELIABLE_CONST char *, RELIABLE_CONST char * file, int line );
 
 #ifndef NDEBUG
 #define reliable_assert( condition )                                                        \
@@ -0,0 +1,70 @@
+//! Measures the `Endpoint` hot paths that lean on `SequenceBuffer`: recording the sent packet,
+//! accepting a received one, building ack bits and walking the acks of the peer's header.
+//!
+//! Power-of-two buffer sizes index by masking the sequence, other sizes unroll its wraps, so
+//! each size is run next to a nearby one of the other kind.
+//!
+//! Run with `cargo bench --bench sequence_buffer`.
+
+use reliable::{Endpoint, EndpointConfig};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const PACKETS: usize = 50_000;
+const RUNS: usize = 5;
+
+/// Exchanges `PACKETS` packets each way between two endpoints, losing one in 16 on the way in.
+fn exchange(buffer_size: usize) -> usize {
+    let mut config = EndpointConfig::new("bench");
+    config.sent_packets_buffer_size = buffer_size;
+    config.received_packets_buffer_size = buffer_size;
+    let mut one = Endpoint::new(config.clone(), 0.0).unwrap();
+    let mut two = Endpoint::new(config, 0.0).unwrap();
+
+    let mut acked = 0;
+    for n in 0..PACKETS {
+        let lost = n % 16 == 0;
+        acked += send(&mut one, &mut two, lost);
+        acked += send(&mut two, &mut one, lost);
+
+        let time = n as f64 * 0.001;
+        one.update(time);
+        two.update(time);
+    }
+    acked
+}
+
+/// Sends one packet from `from` to `to`, returning how many of its packets `from` saw acked.
+fn send(from: &mut Endpoint, to: &mut Endpoint, lost: bool) -> usize {
+    for packet in from.send(&[0x41; 1200]).unwrap() {
+        if !lost {
+            for data in to.recv(&packet).unwrap() {
+                to.release_buffer(data);
+            }
+        }
+        from.release_buffer(packet);
+    }
+    let acked = from.acks().len();
+    from.clear_acks();
+    acked
+}
+
+fn measure(name: &str, buffer_size: usize) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        black_box(exchange(buffer_size));
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "{name:<24} {:>8.1} ns/packet",
+        best.as_secs_f64() * 1e9 / (2 * PACKETS) as f64
+    );
+}
+
+fn main() {
+    measure("buffers of 1024 (mask)", 1024);
+    measure("buffers of 1000", 1000);
+    measure("buffers of 256 (mask)", 256);
+    measure("buffers of 250", 250);
+}
@@ -1,24 +1,49 @@
-use std::env;
-use std::path::PathBuf;
-
 fn main() {
-    // Compile the library
-    cc::Build::new()
-        .file("reliable.c")
-        .define("RELIABLE_ENABLE_TESTS", Some("0"))
-        .define("NDEBUG", Some("0"))
-        .compile("libreliable.a");
+    // The C library is only built with the `c-backend` feature, the pure-Rust endpoint needs
+    // neither a C compiler nor libclang.
+    #[cfg(feature = "c-backend")]
+    c_backend();
+}
 
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+#[cfg(feature = "c-backend")]
+fn c_backend() {
+    use std::env;
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-changed=reliable.c");
+    println!("cargo:rerun-if-changed=reliable.h");
+    println!("cargo:rerun-if-changed=rust/src/bindings.rs");
+
+    // Compile the library. Its asserts are kept in builds with debug assertions, like
+    // `debug_assert!`.
+    let mut build = cc::Build::new();
+    build
+        .file("reliable.c")
+        .define("RELIABLE_ENABLE_TESTS", Some("0"));
+    if env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_none() {
+        build.define("NDEBUG", None);
+    }
+    build.compile("libreliable.a");
 
-    // Build the wrapper bindings librt_transports
-    let bindings = bindgen::Builder::default()
-        .header("reliable.h")
-        //.rustfmt_bindings(true)
-        .generate()
-        .expect("Unable to generate bindings");
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("private_bindings.rs");
 
-    bindings
-        .write_to_file(out_path.join("private_bindings.rs"))
-        .expect("Couldn't write bindings!");
+    // Build the wrapper bindings. Without libclang the pregenerated bindings are used instead,
+    // any other bindgen failure fails the build.
+    match clang_sys::load() {
+        Ok(()) => bindgen::Builder::default()
+            .header("reliable.h")
+            //.rustfmt_bindings(true)
+            .generate()
+            .expect("Unable to generate bindings")
+            .write_to_file(&out_path)
+            .expect("Couldn't write bindings!"),
+        Err(e) => {
+            println!(
+                "cargo:warning=libclang unavailable ({}), using pregenerated rust/src/bindings.rs",
+                e
+            );
+            std::fs::copy("rust/src/bindings.rs", &out_path)
+                .expect("Couldn't copy pregenerated bindings!");
+        }
+    }
 }
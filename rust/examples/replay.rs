@@ -0,0 +1,46 @@
+//! Replays a pcap capture into a fresh `Endpoint`, receiving the incoming datagrams and recording
+//! the outgoing ones as sent, and prints what each datagram delivered and acked, with any errors,
+//! followed by the endpoint's stats.
+//!
+//! Run with `cargo run --example replay -- <capture.pcap> [local address] [name]`. The local
+//! address is the endpoint's side of the capture and defaults to 10.0.0.1:40000, which is what
+//! `PcapWriter` records. The endpoint uses the default config.
+
+use reliable::{replay, EndpointConfig, PcapReader};
+use std::net::SocketAddrV4;
+use std::process;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: replay <capture.pcap> [local address] [name]");
+        process::exit(2);
+    };
+
+    let mut reader = PcapReader::open(&path).unwrap_or_else(|e| {
+        eprintln!("failed to open {path}: {e}");
+        process::exit(1);
+    });
+    if let Some(local) = args.next() {
+        let local: SocketAddrV4 = local.parse().unwrap_or_else(|e| {
+            eprintln!("invalid local address {local}: {e}");
+            process::exit(2);
+        });
+        reader = reader.with_local_address(local);
+    }
+    let name = args.next().unwrap_or_else(|| "replay".to_string());
+
+    let datagrams = reader
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap_or_else(|e| {
+            eprintln!("failed to read {path}: {e}");
+            process::exit(1);
+        });
+    match replay(EndpointConfig::new(&name), datagrams) {
+        Ok(report) => println!("{report}"),
+        Err(e) => {
+            eprintln!("invalid config: {e:?}");
+            process::exit(1);
+        }
+    }
+}
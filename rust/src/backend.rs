@@ -0,0 +1,55 @@
+//! The interface shared by the pure-Rust `Endpoint` and the C-backed
+//! `binding_version::BufferedEndpoint`, so application code can be generic over the backend.
+
+use crate::ReliableError;
+use alloc::vec::Vec;
+
+/// Totals an endpoint keeps of the packets it sent and received. `reset` leaves them alone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Counters {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub packets_acked: u64,
+    pub packets_stale: u64,
+    pub packets_invalid: u64,
+    pub packets_too_large_to_send: u64,
+    pub packets_too_large_to_receive: u64,
+    pub fragments_sent: u64,
+    pub fragments_received: u64,
+    pub fragments_invalid: u64,
+}
+
+/// An endpoint of either backend.
+///
+/// Datagrams go in and out as buffers: `send` returns what to put on the wire and `receive`
+/// returns the packets a datagram completed.
+pub trait ReliableEndpoint {
+    /// Packs `packet` into datagrams. Empty packets are refused with
+    /// `ReliableError::PacketTooSmall`.
+    fn send(&mut self, packet: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError>;
+
+    fn receive(&mut self, datagram: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError>;
+
+    /// Moves the endpoint's clock to `time`, in seconds, and refreshes loss and bandwidth.
+    fn update(&mut self, time: f64);
+
+    /// Extended 32-bit sequences of the sent packets acked since the last `clear_acks`. The low
+    /// 16 bits are the sequence on the wire.
+    fn acks(&self) -> Vec<u32>;
+
+    fn clear_acks(&mut self);
+
+    fn reset(&mut self);
+
+    /// Smoothed round trip time in milliseconds.
+    fn rtt(&self) -> f32;
+
+    /// Smoothed percentage of sent packets that were not acked.
+    fn packet_loss(&self) -> f32;
+
+    /// Smoothed sent, received and acked bandwidth in kbps.
+    fn bandwidth(&self) -> (f32, f32, f32);
+
+    fn counters(&self) -> Counters;
+}
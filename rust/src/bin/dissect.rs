@@ -0,0 +1,325 @@
+//! Decodes reliable.io datagrams and prints their headers.
+//!
+//! Usage: `reliable-dissect [--hex | --binary | --pcap] [FILE]`
+//!
+//! Reads stdin when no file (or `-`) is given. Without a format flag, pcap captures are
+//! recognised by their magic number, input made of hex digits and whitespace is read as hex with
+//! one datagram per line, and anything else is a single binary datagram.
+
+use reliable::{
+    AckFormat, AppHeaderFraming, Cursor, Direction, FragmentHeader, Header, PacketHeader,
+    PcapReader, ReliableError,
+};
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::io::{self, Read};
+use std::process;
+
+/// A datagram and where it came from, for the output.
+type Labelled = (Option<String>, Vec<u8>);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Hex,
+    Binary,
+    Pcap,
+}
+
+fn main() {
+    let mut format = None;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--hex" => format = Some(Format::Hex),
+            "--binary" => format = Some(Format::Binary),
+            "--pcap" => format = Some(Format::Pcap),
+            "-h" | "--help" => usage(0),
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(2),
+        }
+    }
+
+    let input = match path.as_deref() {
+        None | Some("-") => {
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input).map(|_| input)
+        }
+        Some(path) => std::fs::read(path),
+    };
+    let input = input.unwrap_or_else(|e| {
+        eprintln!("failed to read input: {e}");
+        process::exit(1);
+    });
+
+    let format = format.unwrap_or_else(|| detect_format(&input));
+    let datagrams = match format {
+        Format::Pcap => read_pcap(&input),
+        Format::Hex => read_hex(&input),
+        Format::Binary => Ok(vec![(None, input)]),
+    };
+    let datagrams = datagrams.unwrap_or_else(|e| {
+        eprintln!("invalid input: {e}");
+        process::exit(1);
+    });
+
+    for (index, (label, datagram)) in datagrams.iter().enumerate() {
+        print!("#{}", index + 1);
+        if let Some(label) = label {
+            print!(" {label}");
+        }
+        println!(" {} bytes", datagram.len());
+        print!("{}", dissect(datagram));
+    }
+}
+
+fn usage(code: i32) -> ! {
+    eprintln!("usage: reliable-dissect [--hex | --binary | --pcap] [FILE]");
+    process::exit(code);
+}
+
+fn detect_format(input: &[u8]) -> Format {
+    if PcapReader::new(input).is_ok() {
+        Format::Pcap
+    } else if is_hex(input) {
+        Format::Hex
+    } else {
+        Format::Binary
+    }
+}
+
+/// Whether `input` is hex text: at least one hex digit, and nothing but hex digits, whitespace
+/// and `:` outside of `#` comments.
+fn is_hex(input: &[u8]) -> bool {
+    let mut digits = false;
+    for line in input.split(|&c| c == b'\n') {
+        let line = line.split(|&c| c == b'#').next().unwrap_or_default();
+        for &c in line {
+            if c.is_ascii_hexdigit() {
+                digits = true;
+            } else if !c.is_ascii_whitespace() && c != b':' {
+                return false;
+            }
+        }
+    }
+    digits
+}
+
+fn read_pcap(input: &[u8]) -> Result<Vec<Labelled>, String> {
+    PcapReader::new(input)
+        .map_err(|e| e.to_string())?
+        .with_all_datagrams()
+        .map(|captured| {
+            let captured = captured.map_err(|e| e.to_string())?;
+            let arrow = match captured.direction {
+                Direction::Outgoing => "->",
+                Direction::Incoming => "<-",
+            };
+            Ok((
+                Some(format!("{:.6} {}", captured.time, arrow)),
+                captured.datagram,
+            ))
+        })
+        .collect()
+}
+
+/// One datagram per non-empty line. Bytes may be separated by spaces or colons and `#` starts
+/// a comment.
+fn read_hex(input: &[u8]) -> Result<Vec<Labelled>, String> {
+    let input = std::str::from_utf8(input).map_err(|e| e.to_string())?;
+    let mut datagrams = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let digits = line
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != ':')
+            .map(|c| {
+                c.to_digit(16)
+                    .and_then(|digit| u8::try_from(digit).ok())
+                    .ok_or_else(|| format!("line {}: {:?} is not a hex digit", number + 1, c))
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        if digits.is_empty() {
+            continue;
+        }
+        if !digits.len().is_multiple_of(2) {
+            return Err(format!("line {}: odd number of hex digits", number + 1));
+        }
+        let datagram = digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect();
+        datagrams.push((Some(format!("line {}", number + 1)), datagram));
+    }
+    Ok(datagrams)
+}
+
+/// Describes the headers of `datagram`, stopping at the first parse error.
+fn dissect(datagram: &[u8]) -> String {
+    let mut out = String::new();
+    if let Err(e) = dissect_into(&mut out, datagram) {
+        let _ = writeln!(out, "  error: {e:?}");
+    }
+    out
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn dissect_into(out: &mut String, datagram: &[u8]) -> Result<(), ReliableError> {
+    let mut reader = Cursor::new(datagram);
+    let prefix_byte = *datagram.first().ok_or(ReliableError::PacketTooSmall)?;
+
+    let packet_header = if prefix_byte & 1 == 0 {
+        Some(PacketHeader::parse(&mut reader)?)
+    } else {
+        let fragment = FragmentHeader::parse(&mut reader)?;
+        let _ = writeln!(
+            out,
+            "  fragment: sequence {}, id {} of {}, {:?} format",
+            fragment.sequence(),
+            fragment.id(),
+            fragment.count(),
+            fragment.format()
+        );
+        fragment.packet_header().cloned()
+    };
+
+    if let Some(header) = &packet_header {
+        write_packet_header(out, header);
+        match header.app_header() {
+            Some(AppHeaderFraming::LengthPrefixed) => {
+                let position = reader.position() as usize;
+                let length = *datagram
+                    .get(position)
+                    .ok_or(ReliableError::InvalidAppHeader)?;
+                let _ = writeln!(out, "  app header: {length} bytes, length prefixed");
+                reader.set_position((position + 1 + usize::from(length)) as u64);
+            }
+            Some(AppHeaderFraming::Fixed) => {
+                let _ = writeln!(
+                    out,
+                    "  app header: fixed size from the endpoint config, counted in the payload"
+                );
+            }
+            None => {}
+        }
+    }
+
+    let position = reader.position() as usize;
+    if position > datagram.len() {
+        return Err(ReliableError::InvalidAppHeader);
+    }
+    let _ = writeln!(out, "  payload: {} bytes", datagram.len() - position);
+    Ok(())
+}
+
+fn write_packet_header(out: &mut String, header: &PacketHeader) {
+    let bits = header.ack_format().bits();
+    let _ = writeln!(
+        out,
+        "  packet: sequence {}, ack {}, {:?} acks",
+        header.sequence(),
+        header.ack(),
+        header.ack_format()
+    );
+
+    // Bit i acks `ack - i`, so the bitmap reads from the newest ack back.
+    let mut bitmap = String::new();
+    for i in 0..bits {
+        if i > 0 && i % 8 == 0 {
+            bitmap.push(' ');
+        }
+        bitmap.push(if header.wide_ack_bits() >> i & 1 == 1 {
+            '1'
+        } else {
+            '0'
+        });
+    }
+    let width = if header.ack_format() == AckFormat::Wide {
+        16
+    } else {
+        8
+    };
+    let _ = writeln!(
+        out,
+        "  ack_bits: {:#0w$x} (from ack back: {})",
+        header.wide_ack_bits(),
+        bitmap,
+        w = width + 2
+    );
+
+    let missing: Vec<u16> = (0..bits)
+        .filter(|&i| header.wide_ack_bits() >> i & 1 == 0)
+        .map(|i| header.ack().wrapping_sub(i))
+        .collect();
+    if !missing.is_empty() {
+        let _ = writeln!(out, "  not acked: {missing:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write<H: Header>(header: &H) -> Vec<u8> {
+        let mut buffer = vec![0; header.size()];
+        header
+            .write(&mut Cursor::new(buffer.as_mut_slice()))
+            .unwrap();
+        buffer
+    }
+
+    #[test]
+    fn dissect_headers() {
+        let mut packet = write(&PacketHeader::new(12, 10, 0xFFFF_FFFD));
+        packet.extend_from_slice(&[0x41; 20]);
+        assert_eq!(
+            dissect(&packet),
+            "  packet: sequence 12, ack 10, Narrow acks\n\
+             \x20 ack_bits: 0xfffffffd (from ack back: 10111111 11111111 11111111 11111111)\n\
+             \x20 not acked: [9]\n\
+             \x20 payload: 20 bytes\n"
+        );
+
+        let header = PacketHeader::new_wide(7, 6, u64::MAX)
+            .with_app_header(AppHeaderFraming::LengthPrefixed);
+        let mut fragment = write(&FragmentHeader::new_wide(0, 3, header));
+        fragment.extend_from_slice(&[2, 0xAA, 0xBB, 0x41, 0x41]);
+        let dissected = dissect(&fragment);
+        assert!(dissected.starts_with("  fragment: sequence 7, id 0 of 3, Wide format\n"));
+        assert!(dissected.contains("  packet: sequence 7, ack 6, Wide acks\n"));
+        assert!(dissected.contains("  ack_bits: 0xffffffffffffffff"));
+        assert!(dissected.contains("  app header: 2 bytes, length prefixed\n"));
+        assert!(dissected.ends_with("  payload: 2 bytes\n"));
+
+        let fragment = write(&FragmentHeader::new_fragment(2, 3, 7));
+        assert_eq!(
+            dissect(&fragment),
+            "  fragment: sequence 7, id 2 of 3, Narrow format\n  payload: 0 bytes\n"
+        );
+
+        assert_eq!(dissect(&[]), "  error: PacketTooSmall\n");
+        assert_eq!(dissect(&[0x01, 7, 0, 3, 3]), "  error: InvalidFragment\n");
+    }
+
+    #[test]
+    fn hex_input() {
+        let input = b"00 0c 0a:41\n\n0102\n";
+        assert!(detect_format(input) == Format::Hex);
+        assert!(detect_format(&[0x00, 0x0c, 0x0a, 0x41]) == Format::Binary);
+        assert!(detect_format(b"# comment\n0102 # trailing\n") == Format::Hex);
+        assert!(detect_format(b"# comment only\n") == Format::Binary);
+
+        let datagrams = read_hex(input).unwrap();
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0].1, vec![0x00, 0x0c, 0x0a, 0x41]);
+        assert_eq!(datagrams[1].0.as_deref(), Some("line 3"));
+        assert_eq!(
+            read_hex(b"# comment\n0102 # trailing\n").unwrap()[0].1,
+            vec![1, 2]
+        );
+        assert!(read_hex(b"abc").is_err());
+        assert!(read_hex(b"0g").is_err());
+        // Multi-byte characters are refused rather than split.
+        assert_eq!(
+            read_hex("0\u{e9}".as_bytes()).unwrap_err(),
+            "line 1: '\u{e9}' is not a hex digit"
+        );
+        assert!(read_hex("\u{e9}00".as_bytes()).is_err());
+    }
+}
@@ -1,51 +1,44 @@
-#![cfg_attr(
-    feature = "cargo-clippy",
-    allow(clippy, clippy_correctness, clippy_style, clippy_pedantic, clippy_perf)
-)]
+#![allow(clippy::all, clippy::correctness, clippy::style, clippy::pedantic, clippy::perf)]
 
 use log::*;
 
 use crate::capi;
 use crate::capi::*;
-use std::sync::Arc;
+use crate::sequence_buffer;
+use crate::{ConfigError, ReliableEndpoint, ReliableError};
+use std::alloc::{self, Layout};
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
 
-#[derive(Clone)]
+/// Owns a C endpoint and destroys it on drop.
 pub struct EndpointHandle {
     pointer: *mut reliable_endpoint_t,
 }
 
-impl Default for EndpointHandle {
-    fn default() -> Self {
-        Self {
-            pointer: std::ptr::null_mut(),
-        }
-    }
-}
-
 impl EndpointHandle {
-    pub fn new(config: &Config) -> Self {
-        let pointer;
-        unsafe {
-            pointer = capi::reliable_endpoint_create(config, 100.0);
+    /// Creates a C endpoint from `config`.
+    ///
+    /// Also installs the C assert function, once per process, so a failed C assert is reported
+    /// as a panic before the process aborts.
+    ///
+    /// # Safety
+    ///
+    /// The C endpoint calls back through `config.context` and `config.allocator_context`, so
+    /// whatever those point at must stay alive until the returned handle is dropped.
+    pub unsafe fn new(config: &Config) -> Self {
+        install_assert_function();
+        Self {
+            pointer: capi::reliable_endpoint_create(config, 100.0),
         }
-        Self { pointer }
     }
 
-    pub fn set(&mut self, ptr: *mut reliable_endpoint_t) {
-        self.pointer = ptr;
-    }
     pub fn ptr(&self) -> *mut reliable_endpoint_t {
         self.pointer
     }
-    pub fn ptr_mut(&mut self) -> *mut reliable_endpoint_t {
-        self.pointer
-    }
-}
-
-impl Into<*mut reliable_endpoint_t> for EndpointHandle {
-    fn into(self) -> *mut reliable_endpoint_t {
-        self.ptr()
-    }
 }
 
 impl Drop for EndpointHandle {
@@ -58,7 +51,7 @@ impl Drop for EndpointHandle {
 }
 
 pub trait Endpoint {
-    fn handle(&self) -> Arc<EndpointHandle>;
+    fn handle(&self) -> &EndpointHandle;
 
     fn reset(&mut self) {
         trace!("reset");
@@ -142,67 +135,143 @@ pub trait Endpoint {
 
         (res[0], res[1], res[2])
     }
+
+    fn counters(&self) -> Counters {
+        let counters;
+
+        unsafe {
+            let ptr = capi::reliable_endpoint_counters(self.handle().ptr());
+            counters = std::slice::from_raw_parts(ptr, RELIABLE_ENDPOINT_NUM_COUNTERS as usize);
+        }
+
+        Counters::from_raw(counters)
+    }
 }
 
-// Exposed function to the user of the bindings
-pub fn create_packet_function<T, P>(config: &mut Config, transmit_packet: T, process_packet: P)
+/// Totals kept by the C endpoint, read from `reliable_endpoint_counters`.
+pub use crate::Counters;
+
+impl Counters {
+    fn from_raw(counters: &[u64]) -> Self {
+        let counter = |index: u32| counters[index as usize];
+        Self {
+            packets_sent: counter(RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_SENT),
+            packets_received: counter(RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_RECEIVED),
+            packets_acked: counter(RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_ACKED),
+            packets_stale: counter(RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_STALE),
+            packets_invalid: counter(RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_INVALID),
+            packets_too_large_to_send: counter(
+                RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_TOO_LARGE_TO_SEND,
+            ),
+            packets_too_large_to_receive: counter(
+                RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_TOO_LARGE_TO_RECEIVE,
+            ),
+            fragments_sent: counter(RELIABLE_ENDPOINT_COUNTER_NUM_FRAGMENTS_SENT),
+            fragments_received: counter(RELIABLE_ENDPOINT_COUNTER_NUM_FRAGMENTS_RECEIVED),
+            fragments_invalid: counter(RELIABLE_ENDPOINT_COUNTER_NUM_FRAGMENTS_INVALID),
+        }
+    }
+}
+
+/// Packet callbacks installed into a `Config` by `create_packet_function` or
+/// `create_packet_handler`.
+///
+/// The C endpoint calls back through `config.context`, which points at the handler owned here,
+/// so this must outlive every endpoint created from that config. Dropping it frees the handler.
+pub struct PacketFunctions<'a> {
+    context: NonNull<PacketContext<'a>>,
+}
+
+struct PacketContext<'a> {
+    handler: Box<dyn EndpointHandler + 'a>,
+}
+
+impl<'a> Drop for PacketFunctions<'a> {
+    fn drop(&mut self) {
+        trace!("PacketFunctions dropped, freeing callback context");
+        unsafe {
+            drop(Box::from_raw(self.context.as_ptr()));
+        }
+    }
+}
+
+/// Installs `transmit_packet` and `process_packet` as the packet callbacks of `config`.
+///
+/// # Safety
+///
+/// The returned `PacketFunctions` must outlive every endpoint created from `config`.
+#[must_use = "the callbacks are freed when the returned PacketFunctions is dropped"]
+pub unsafe fn create_packet_function<'a, T, P>(
+    config: &mut Config,
+    transmit_packet: T,
+    process_packet: P,
+) -> PacketFunctions<'a>
 where
-    T: Fn(i32, u16, &[u8]),
-    P: Fn(i32, u16, &[u8]) -> i32,
+    T: Fn(i32, u16, &[u8]) + 'a,
+    P: Fn(i32, u16, &[u8]) -> i32 + 'a,
 {
-    struct Context<T, P> {
-        transmit_packet: T,
-        process_packet: P,
-    }
-    let arg_context = Box::new(Context {
-        transmit_packet: &transmit_packet,
-        process_packet: &process_packet,
-    });
+    create_packet_handler(
+        config,
+        Box::new(ClosureHandler {
+            transmit_packet,
+            process_packet,
+        }),
+    )
+}
+
+/// Installs `handler` as the packet callbacks of `config`.
+///
+/// # Safety
+///
+/// The returned `PacketFunctions` must outlive every endpoint created from `config`.
+#[must_use = "the handler is freed when the returned PacketFunctions is dropped"]
+pub unsafe fn create_packet_handler<'a>(
+    config: &mut Config,
+    handler: Box<dyn EndpointHandler + 'a>,
+) -> PacketFunctions<'a> {
+    // Leaked into a raw pointer rather than kept as a `Box`, so moving `PacketFunctions` around
+    // never invalidates the pointer the C side holds.
+    let context = NonNull::new_unchecked(Box::into_raw(Box::new(PacketContext { handler })));
 
-    //ffi::do_thing(transmit_packet_function_wrapper::<F>, user_data);
-    config.context = Box::into_raw(arg_context) as *mut std::os::raw::c_void;
-    config.transmit_packet_function = Some(transmit_packet_wrapper::<T, P>);
-    config.process_packet_function = Some(process_packet_wrapper::<P, T>);
+    config.context = context.as_ptr() as *mut std::os::raw::c_void;
+    config.transmit_packet_function = Some(transmit_packet_wrapper);
+    config.process_packet_function = Some(process_packet_wrapper);
 
-    // Shim interface function
-    extern "C" fn transmit_packet_wrapper<T, U>(
+    // Shim interface functions. They only borrow the context; it is freed by `PacketFunctions`.
+    unsafe extern "C" fn transmit_packet_wrapper(
         context: *mut std::os::raw::c_void,
         index: std::os::raw::c_int,
         sequence: u16,
         buffer: *const u8,
         size: std::os::raw::c_int,
-    ) where
-        T: Fn(i32, u16, &[u8]),
-    {
-        unsafe {
-            let opt_context = Box::from_raw(context as *mut Context<T, U>);
-
-            (opt_context.transmit_packet)(
-                index as i32,
-                sequence,
-                std::slice::from_raw_parts(buffer, size as usize),
-            );
-        }
+    ) {
+        let context = &*(context as *const PacketContext<'_>);
+        context
+            .handler
+            .on_transmit_packet(index as i32, sequence, packet_slice(buffer, size));
     }
 
-    extern "C" fn process_packet_wrapper<P, U>(
+    unsafe extern "C" fn process_packet_wrapper(
         context: *mut std::os::raw::c_void,
         index: std::os::raw::c_int,
         sequence: u16,
         buffer: *const u8,
         size: std::os::raw::c_int,
-    ) -> i32
-    where
-        P: Fn(i32, u16, &[u8]) -> i32,
-    {
-        unsafe {
-            let opt_context = Box::from_raw(context as *mut Context<U, P>);
-            (opt_context.process_packet)(
-                index,
-                sequence,
-                std::slice::from_raw_parts(buffer, size as usize),
-            )
-        }
+    ) -> std::os::raw::c_int {
+        let context = &*(context as *const PacketContext<'_>);
+        context
+            .handler
+            .on_process_packet(index as i32, sequence, packet_slice(buffer, size))
+    }
+
+    PacketFunctions { context }
+}
+
+unsafe fn packet_slice<'b>(buffer: *const u8, size: std::os::raw::c_int) -> &'b [u8] {
+    if buffer.is_null() || size <= 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(buffer, size as usize)
     }
 }
 
@@ -211,50 +280,516 @@ pub trait EndpointHandler {
     fn on_process_packet(&self, index: i32, sequence: u16, data: &[u8]) -> i32;
 }
 
+impl<'h, H: EndpointHandler + ?Sized> EndpointHandler for &'h H {
+    fn on_transmit_packet(&self, index: i32, sequence: u16, data: &[u8]) {
+        (**self).on_transmit_packet(index, sequence, data)
+    }
+    fn on_process_packet(&self, index: i32, sequence: u16, data: &[u8]) -> i32 {
+        (**self).on_process_packet(index, sequence, data)
+    }
+}
+
+struct ClosureHandler<T, P> {
+    transmit_packet: T,
+    process_packet: P,
+}
+
+impl<T, P> EndpointHandler for ClosureHandler<T, P>
+where
+    T: Fn(i32, u16, &[u8]),
+    P: Fn(i32, u16, &[u8]) -> i32,
+{
+    fn on_transmit_packet(&self, index: i32, sequence: u16, data: &[u8]) {
+        (self.transmit_packet)(index, sequence, data)
+    }
+    fn on_process_packet(&self, index: i32, sequence: u16, data: &[u8]) -> i32 {
+        (self.process_packet)(index, sequence, data)
+    }
+}
+
+/// Bytes the C library has allocated for one endpoint, see `SimpleEndpoint::memory_usage`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Bytes currently allocated and not yet freed.
+    pub bytes_outstanding: usize,
+    /// The most bytes that were outstanding at any one time.
+    pub peak_bytes: usize,
+}
+
+/// Allocator callbacks installed into a `Config` by `create_allocator`.
+///
+/// The C endpoint allocates and frees through `config.allocator_context`, which points at the
+/// accounting owned here, so like `PacketFunctions` this must outlive every endpoint created from
+/// that config. Dropping it frees the accounting.
+pub struct AllocatorFunctions {
+    allocations: NonNull<Allocations>,
+}
+
+impl AllocatorFunctions {
+    pub fn usage(&self) -> MemoryUsage {
+        unsafe { self.allocations.as_ref() }.usage()
+    }
+}
+
+impl Drop for AllocatorFunctions {
+    fn drop(&mut self) {
+        trace!("AllocatorFunctions dropped, freeing allocator context");
+        unsafe {
+            drop(Box::from_raw(self.allocations.as_ptr()));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Allocations {
+    bytes_outstanding: AtomicUsize,
+    peak_bytes: AtomicUsize,
+}
+
+impl Allocations {
+    fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            bytes_outstanding: self.bytes_outstanding.load(Ordering::Relaxed),
+            peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Every allocation is prefixed with its size, which `free_function` is not given. Sixteen bytes
+// keeps the pointer handed to C aligned like `malloc`'s.
+const ALLOCATION_HEADER: usize = 16;
+
+/// Installs allocator callbacks into `config` that allocate through the Rust global allocator and
+/// count the bytes.
+///
+/// Fails with `ConfigError::AllocatorAlreadySet` if the config already has its own allocator
+/// functions, since the bytes they hand out could not be counted.
+///
+/// # Safety
+///
+/// The returned `AllocatorFunctions` must outlive every endpoint created from `config`.
+pub unsafe fn create_allocator(config: &mut Config) -> Result<AllocatorFunctions, ReliableError> {
+    if config.allocate_function.is_some() || config.free_function.is_some() {
+        return Err(ConfigError::AllocatorAlreadySet.into());
+    }
+
+    // Leaked like the packet context, so moving `AllocatorFunctions` never invalidates the
+    // pointer the C side holds.
+    let allocations = NonNull::new_unchecked(Box::into_raw(Box::<Allocations>::default()));
+    config.allocator_context = allocations.as_ptr() as *mut std::os::raw::c_void;
+    config.allocate_function = Some(allocate_wrapper);
+    config.free_function = Some(free_wrapper);
+
+    unsafe extern "C" fn allocate_wrapper(
+        context: *mut std::os::raw::c_void,
+        bytes: u64,
+    ) -> *mut std::os::raw::c_void {
+        let allocations = &*(context as *const Allocations);
+        let size = match usize::try_from(bytes) {
+            Ok(size) => size,
+            Err(_) => return std::ptr::null_mut(),
+        };
+        let layout = match size
+            .checked_add(ALLOCATION_HEADER)
+            .and_then(|total| Layout::from_size_align(total, ALLOCATION_HEADER).ok())
+        {
+            Some(layout) => layout,
+            None => return std::ptr::null_mut(),
+        };
+
+        let base = alloc::alloc(layout);
+        if base.is_null() {
+            return std::ptr::null_mut();
+        }
+        (base as *mut usize).write(size);
+
+        let outstanding = allocations
+            .bytes_outstanding
+            .fetch_add(size, Ordering::Relaxed)
+            + size;
+        allocations
+            .peak_bytes
+            .fetch_max(outstanding, Ordering::Relaxed);
+
+        base.add(ALLOCATION_HEADER) as *mut std::os::raw::c_void
+    }
+
+    unsafe extern "C" fn free_wrapper(
+        context: *mut std::os::raw::c_void,
+        pointer: *mut std::os::raw::c_void,
+    ) {
+        if pointer.is_null() {
+            return;
+        }
+        let allocations = &*(context as *const Allocations);
+        let base = (pointer as *mut u8).sub(ALLOCATION_HEADER);
+        let size = (base as *const usize).read();
+
+        allocations
+            .bytes_outstanding
+            .fetch_sub(size, Ordering::Relaxed);
+        alloc::dealloc(
+            base,
+            Layout::from_size_align_unchecked(size + ALLOCATION_HEADER, ALLOCATION_HEADER),
+        );
+    }
+
+    Ok(AllocatorFunctions { allocations })
+}
+
+/// A C endpoint together with the callbacks and allocator it calls through.
+///
+/// It is the only owner of all three and is not `Clone`, so the C endpoint cannot outlive them.
 pub struct SimpleEndpoint<'a> {
-    handle: Arc<EndpointHandle>,
+    // Declared first so the C endpoint is destroyed before its callbacks and allocator.
+    handle: EndpointHandle,
     handler: Option<&'a dyn EndpointHandler>,
     config: Config,
+    packet_functions: PacketFunctions<'a>,
+    allocator_functions: AllocatorFunctions,
 }
 
 impl<'a> SimpleEndpoint<'a> {
-    pub fn new(mut config: Config, handler: &'a dyn EndpointHandler) -> Self {
-        create_packet_function(
-            &mut config,
-            |a, b, c| handler.on_transmit_packet(a, b, c),
-            |a, b, c| handler.on_process_packet(a, b, c),
+    pub fn new(
+        mut config: Config,
+        handler: &'a dyn EndpointHandler,
+    ) -> Result<Self, ReliableError> {
+        // Safety: the endpoint drops its C handle before its packet functions.
+        let packet_functions = unsafe { create_packet_handler(&mut config, Box::new(handler)) };
+        Self::create(config, Some(handler), packet_functions)
+    }
+
+    pub fn new_closure<T, P>(
+        mut config: Config,
+        transmit_packet: T,
+        process_packet: P,
+    ) -> Result<Self, ReliableError>
+    where
+        T: Fn(i32, u16, &[u8]) + 'a,
+        P: Fn(i32, u16, &[u8]) -> i32 + 'a,
+    {
+        // Safety: the endpoint drops its C handle before its packet functions.
+        let packet_functions =
+            unsafe { create_packet_function(&mut config, transmit_packet, process_packet) };
+        Self::create(config, None, packet_functions)
+    }
+
+    fn create(
+        mut config: Config,
+        handler: Option<&'a dyn EndpointHandler>,
+        packet_functions: PacketFunctions<'a>,
+    ) -> Result<Self, ReliableError> {
+        // Safety: the allocator and packet functions are owned by the endpoint too, and are
+        // dropped after the handle.
+        unsafe {
+            let allocator_functions = create_allocator(&mut config)?;
+            Ok(Self {
+                handle: EndpointHandle::new(&config),
+                handler,
+                config,
+                packet_functions,
+                allocator_functions,
+            })
+        }
+    }
+
+    /// Memory the C library currently holds for this endpoint, and its peak.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.allocator_functions.usage()
+    }
+}
+
+impl<'a> Endpoint for SimpleEndpoint<'a> {
+    fn handle(&self) -> &EndpointHandle {
+        &self.handle
+    }
+}
+
+/// A C-backed endpoint that owns its handler, so it borrows nothing and can be stored in
+/// long-lived structs or moved to another thread.
+///
+/// # Thread safety
+///
+/// `OwnedEndpoint` is `Send` but not `Sync`. The C endpoint keeps no thread-local state and only
+/// calls the handler from inside `send` and `recv`, on whichever thread owns the endpoint at the
+/// time, so the handler itself only has to be `Send`. Taking `&mut self` for those calls keeps two
+/// threads from driving the endpoint at once. The C library's log level and printf hooks are
+/// process-wide and unaffected by which thread an endpoint lives on.
+pub struct OwnedEndpoint {
+    endpoint: SimpleEndpoint<'static>,
+}
+
+// SAFETY: the raw pointers inside are the C endpoint, its packet context and its allocator
+// context. All three are owned by this value alone: `EndpointHandle` is not `Clone`, `handle()`
+// only lends it for the life of a borrow of the endpoint, and the contexts are never handed out.
+// Moving the endpoint moves sole ownership of everything they point at, the C library keeps no
+// per-thread state, and the handler they call into was required to be `Send`.
+unsafe impl Send for OwnedEndpoint {}
+
+impl OwnedEndpoint {
+    pub fn new(
+        mut config: Config,
+        handler: Box<dyn EndpointHandler + Send>,
+    ) -> Result<Self, ReliableError> {
+        // Safety: `SimpleEndpoint` drops its C handle before its packet functions.
+        let packet_functions = unsafe { create_packet_handler(&mut config, handler) };
+
+        Ok(Self {
+            endpoint: SimpleEndpoint::create(config, None, packet_functions)?,
+        })
+    }
+
+    /// Memory the C library currently holds for this endpoint, and its peak.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.endpoint.memory_usage()
+    }
+}
+
+impl Endpoint for OwnedEndpoint {
+    fn handle(&self) -> &EndpointHandle {
+        self.endpoint.handle()
+    }
+}
+
+/// A C-backed endpoint that queues what its callbacks hand it, so it is driven like the Rust
+/// `Endpoint` and implements `ReliableEndpoint`. Like `OwnedEndpoint`, it is `Send`.
+///
+/// The C library does not report errors, so `send` and `receive` derive them from the counters
+/// it bumps on the way.
+pub struct BufferedEndpoint {
+    endpoint: OwnedEndpoint,
+    queues: Arc<Mutex<PacketQueues>>,
+    // The C endpoint only keeps 16-bit sequences; this extends its next one as it wraps.
+    next_sequence: u32,
+}
+
+#[derive(Default)]
+struct PacketQueues {
+    transmitted: Vec<Vec<u8>>,
+    processed: Vec<Vec<u8>>,
+}
+
+struct QueueHandler(Arc<Mutex<PacketQueues>>);
+
+impl EndpointHandler for QueueHandler {
+    fn on_transmit_packet(&self, _: i32, _: u16, data: &[u8]) {
+        self.0.lock().unwrap().transmitted.push(data.to_vec());
+    }
+
+    fn on_process_packet(&self, _: i32, _: u16, data: &[u8]) -> i32 {
+        self.0.lock().unwrap().processed.push(data.to_vec());
+        1
+    }
+}
+
+impl BufferedEndpoint {
+    pub fn new(config: Config) -> Result<Self, ReliableError> {
+        let queues = Arc::new(Mutex::new(PacketQueues::default()));
+        Ok(Self {
+            endpoint: OwnedEndpoint::new(config, Box::new(QueueHandler(queues.clone())))?,
+            queues,
+            next_sequence: 0,
+        })
+    }
+
+    /// Memory the C library currently holds for this endpoint, and its peak.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.endpoint.memory_usage()
+    }
+}
+
+impl ReliableEndpoint for BufferedEndpoint {
+    fn send(&mut self, packet: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
+        // The C library asserts that packets are not empty.
+        if packet.is_empty() {
+            return Err(ReliableError::PacketTooSmall);
+        }
+        let before = Endpoint::counters(&self.endpoint);
+        Endpoint::send(&mut self.endpoint, packet);
+        let transmitted = std::mem::take(&mut self.queues.lock().unwrap().transmitted);
+        self.next_sequence = sequence_buffer::extend_sequence(
+            self.next_sequence,
+            self.endpoint.next_packet_sequence(),
         );
 
-        Self {
-            config,
-            handler: Some(handler),
-            handle: Arc::new(EndpointHandle::new(&config)),
+        if Endpoint::counters(&self.endpoint).packets_too_large_to_send
+            > before.packets_too_large_to_send
+        {
+            return Err(ReliableError::ExceededMaxPacketSize);
         }
+        Ok(transmitted)
     }
 
-    pub fn new_closure<T, P>(mut config: Config, transmit_packet: T, process_packet: P) -> Self
-    where
-        T: Fn(i32, u16, &[u8]),
-        P: Fn(i32, u16, &[u8]) -> i32,
-    {
-        create_packet_function(&mut config, transmit_packet, process_packet);
+    fn receive(&mut self, datagram: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
+        // The C library reads the prefix byte without checking the length.
+        if datagram.is_empty() {
+            return Err(ReliableError::PacketTooSmall);
+        }
+
+        let before = Endpoint::counters(&self.endpoint);
+        Endpoint::recv(&mut self.endpoint, datagram);
+        let processed = std::mem::take(&mut self.queues.lock().unwrap().processed);
+
+        let after = Endpoint::counters(&self.endpoint);
+        if after.packets_too_large_to_receive > before.packets_too_large_to_receive {
+            Err(ReliableError::ExceededMaxPacketSize)
+        } else if after.packets_invalid > before.packets_invalid {
+            Err(ReliableError::InvalidPacket)
+        } else if after.packets_stale > before.packets_stale {
+            Err(ReliableError::StalePacket)
+        } else if after.fragments_invalid > before.fragments_invalid {
+            Err(ReliableError::InvalidFragment)
+        } else {
+            Ok(processed)
+        }
+    }
 
+    fn update(&mut self, time: f64) {
+        Endpoint::update(&mut self.endpoint, time);
+    }
+
+    fn acks(&self) -> Vec<u32> {
+        // Every ack is of a packet sent before the next sequence.
+        let latest = self.next_sequence.wrapping_sub(1);
+        self.endpoint
+            .get_acks()
+            .into_iter()
+            .map(|ack| sequence_buffer::extend_sequence(latest, ack))
+            .collect()
+    }
+
+    fn clear_acks(&mut self) {
+        Endpoint::clear_acks(&mut self.endpoint);
+    }
+
+    fn reset(&mut self) {
+        Endpoint::reset(&mut self.endpoint);
+        self.next_sequence = 0;
+    }
+
+    fn rtt(&self) -> f32 {
+        self.endpoint.current_rtt()
+    }
+
+    fn packet_loss(&self) -> f32 {
+        self.endpoint.current_packet_loss()
+    }
+
+    fn bandwidth(&self) -> (f32, f32, f32) {
+        Endpoint::bandwidth(&self.endpoint)
+    }
+
+    fn counters(&self) -> Counters {
+        Endpoint::counters(&self.endpoint)
+    }
+}
+
+/// Forwards the C library's log lines to the `log` crate.
+///
+/// The C log level is set from `log::max_level()`, so install these after the logger. Lines are
+/// logged under the `reliable::c` target at the level the C library printed them with. Both the
+/// level and the log function are process-wide, so this is left to the application to opt into.
+pub fn install_log_hooks() {
+    unsafe {
+        capi::reliable_log_level(c_log_level(log::max_level()));
+        capi::reliable_set_log_function(Some(log_function));
+    }
+
+    unsafe extern "C" fn log_function(
+        level: std::os::raw::c_int,
+        message: *const std::os::raw::c_char,
+    ) {
+        let level = match level as u32 {
+            RELIABLE_LOG_LEVEL_ERROR => Level::Error,
+            RELIABLE_LOG_LEVEL_INFO => Level::Info,
+            RELIABLE_LOG_LEVEL_DEBUG => Level::Debug,
+            _ => Level::Trace,
+        };
+        let message = CStr::from_ptr(message).to_string_lossy();
+        log!(target: "reliable::c", level, "{}", message.trim_end());
+    }
+}
+
+/// Installs a C assert function that logs the failed assert and panics with it.
+///
+/// The panic cannot unwind out of an `extern "C"` fn, so the process aborts once the panic hook
+/// has reported it. That is on purpose: the C code cannot be unwound through, and carrying on
+/// past an assert can write out of bounds. `reliable_assert` exits after the assert function
+/// returns for the same reason.
+///
+/// Asserts are only compiled into C builds without `NDEBUG`, which build.rs keeps for builds with
+/// debug assertions.
+fn install_assert_function() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe { capi::reliable_set_assert_function(Some(assert_function)) });
+
+    unsafe extern "C" fn assert_function(
+        condition: *const std::os::raw::c_char,
+        function: *const std::os::raw::c_char,
+        file: *const std::os::raw::c_char,
+        line: std::os::raw::c_int,
+    ) {
+        let assertion = CAssertion::from_raw(condition, function, file, line);
+        error!(target: "reliable::c", "{}", assertion);
+        panic!("{}", assertion);
+    }
+}
+
+fn c_log_level(filter: LevelFilter) -> std::os::raw::c_int {
+    let level = match filter {
+        LevelFilter::Off => RELIABLE_LOG_LEVEL_NONE,
+        LevelFilter::Error | LevelFilter::Warn => RELIABLE_LOG_LEVEL_ERROR,
+        LevelFilter::Info => RELIABLE_LOG_LEVEL_INFO,
+        LevelFilter::Debug | LevelFilter::Trace => RELIABLE_LOG_LEVEL_DEBUG,
+    };
+    level as std::os::raw::c_int
+}
+
+/// A failed assert inside the C library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CAssertion {
+    pub condition: String,
+    pub function: String,
+    pub file: String,
+    pub line: i32,
+}
+
+impl CAssertion {
+    unsafe fn from_raw(
+        condition: *const std::os::raw::c_char,
+        function: *const std::os::raw::c_char,
+        file: *const std::os::raw::c_char,
+        line: std::os::raw::c_int,
+    ) -> Self {
+        let string = |pointer: *const std::os::raw::c_char| {
+            if pointer.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(pointer).to_string_lossy().into_owned()
+            }
+        };
         Self {
-            config,
-            handler: None,
-            handle: Arc::new(EndpointHandle::new(&config)),
+            condition: string(condition),
+            function: string(function),
+            file: string(file),
+            line,
         }
     }
 }
 
-impl<'a> Endpoint for SimpleEndpoint<'a> {
-    fn handle(&self) -> Arc<EndpointHandle> {
-        self.handle.clone()
+impl fmt::Display for CAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reliable.io assert failed: ( {} ), function {}, file {}, line {}",
+            self.condition, self.function, self.file, self.line
+        )
     }
 }
 
 pub struct Reliable;
 impl Reliable {
+    /// Initializes the C library. Its log lines are only forwarded after `install_log_hooks`.
     pub fn new() -> Self {
         trace!("Reliable.io initialized");
         unsafe {
@@ -267,7 +802,7 @@ impl Reliable {
         &self,
         config: Config,
         handler: &'a dyn EndpointHandler,
-    ) -> SimpleEndpoint<'a> {
+    ) -> Result<SimpleEndpoint<'a>, ReliableError> {
         SimpleEndpoint::new(config, handler)
     }
     pub fn create_endpoint_closure<'a, T, P>(
@@ -275,13 +810,20 @@ impl Reliable {
         config: Config,
         transmit_packet: T,
         process_packet: P,
-    ) -> SimpleEndpoint<'a>
+    ) -> Result<SimpleEndpoint<'a>, ReliableError>
     where
-        T: Fn(i32, u16, &[u8]),
-        P: Fn(i32, u16, &[u8]) -> i32,
+        T: Fn(i32, u16, &[u8]) + 'a,
+        P: Fn(i32, u16, &[u8]) -> i32 + 'a,
     {
         SimpleEndpoint::new_closure(config, transmit_packet, process_packet)
     }
+    pub fn create_owned_endpoint(
+        &self,
+        config: Config,
+        handler: Box<dyn EndpointHandler + Send>,
+    ) -> Result<OwnedEndpoint, ReliableError> {
+        OwnedEndpoint::new(config, handler)
+    }
 }
 
 impl Drop for Reliable {
@@ -298,17 +840,19 @@ pub use crate::capi::reliable_config_t as Config;
 impl Default for Config {
     fn default() -> Self {
         trace!("Building config from default");
+        let mut res = std::mem::MaybeUninit::<Self>::uninit();
         unsafe {
-            let mut res: Self = std::mem::uninitialized();
-            capi::reliable_default_config(&mut res);
+            capi::reliable_default_config(res.as_mut_ptr());
 
-            res
+            res.assume_init()
         }
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
 
     struct TestHandler;
     impl EndpointHandler for TestHandler {
@@ -345,17 +889,530 @@ mod tests {
         let reliable = Reliable::new();
 
         let handler = TestHandler {};
-        let mut endpoint_1 = reliable.create_endpoint(Config::default(), &handler);
-        let mut endpoint_2 = reliable.create_endpoint_closure(
+        let mut endpoint_1 = reliable
+            .create_endpoint(Config::default(), &handler)
+            .unwrap();
+        let mut endpoint_2 = reliable
+            .create_endpoint_closure(
+                Config::default(),
+                |_, _, _| trace!("enter"),
+                |_, _, _| {
+                    trace!("enter");
+                    1
+                },
+            )
+            .unwrap();
+
+        endpoint_1.update(10.0);
+        endpoint_2.update(10.0);
+    }
+
+    #[test]
+    fn packet_functions_many_callbacks() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        // Drives the callbacks through the config's function pointers without touching the C
+        // library, so this also runs under Miri.
+        let mut config: Config = unsafe { std::mem::zeroed() };
+        let transmitted = Cell::new(0);
+        let processed = Rc::new(Cell::new(0usize));
+        let processed_in_closure = processed.clone();
+
+        let functions = unsafe {
+            create_packet_function(
+                &mut config,
+                |index, sequence, data| {
+                    assert_eq!(index, 7);
+                    assert_eq!(data.len(), sequence as usize % 64);
+                    transmitted.set(transmitted.get() + 1);
+                },
+                move |_, _, data| {
+                    processed_in_closure.set(processed_in_closure.get() + 1);
+                    data.len() as i32
+                },
+            )
+        };
+
+        let transmit = config.transmit_packet_function.unwrap();
+        let process = config.process_packet_function.unwrap();
+        let packet = [0u8; 64];
+        for sequence in 0..1000u16 {
+            let size = sequence as i32 % 64;
+            unsafe {
+                transmit(config.context, 7, sequence, packet.as_ptr(), size);
+                assert_eq!(
+                    process(config.context, 7, sequence, packet.as_ptr(), size),
+                    size
+                );
+            }
+        }
+        assert_eq!(transmitted.get(), 1000);
+        assert_eq!(processed.get(), 1000);
+
+        // The process closure holds the other reference; dropping the functions frees it once.
+        assert_eq!(Rc::strong_count(&processed), 2);
+        drop(functions);
+        assert_eq!(Rc::strong_count(&processed), 1);
+    }
+
+    struct CountingHandler {
+        transmitted: std::cell::Cell<usize>,
+        processed: std::cell::Cell<usize>,
+    }
+    impl EndpointHandler for CountingHandler {
+        fn on_transmit_packet(&self, _: i32, _: u16, data: &[u8]) {
+            assert!(data.iter().all(|&byte| byte == 0x5A));
+            self.transmitted.set(self.transmitted.get() + 1);
+        }
+        fn on_process_packet(&self, _: i32, sequence: u16, _: &[u8]) -> i32 {
+            self.processed.set(self.processed.get() + 1);
+            (sequence % 2) as i32
+        }
+    }
+
+    #[test]
+    fn packet_handler_many_callbacks() {
+        // Like `packet_functions_many_callbacks`, through a borrowed handler and the null buffer
+        // the C library passes for empty packets.
+        let handler = CountingHandler {
+            transmitted: std::cell::Cell::new(0),
+            processed: std::cell::Cell::new(0),
+        };
+        let mut config: Config = unsafe { std::mem::zeroed() };
+        let functions = unsafe { create_packet_handler(&mut config, Box::new(&handler)) };
+
+        let transmit = config.transmit_packet_function.unwrap();
+        let process = config.process_packet_function.unwrap();
+        let packet = [0x5Au8; 256];
+        for sequence in 0..=u16::MAX {
+            let size = sequence as i32 % 256;
+            let buffer = if size == 0 {
+                std::ptr::null()
+            } else {
+                packet.as_ptr()
+            };
+            unsafe {
+                transmit(config.context, 0, sequence, buffer, size);
+                assert_eq!(
+                    process(config.context, 0, sequence, buffer, size),
+                    (sequence % 2) as i32
+                );
+            }
+        }
+        drop(functions);
+
+        assert_eq!(handler.transmitted.get(), 65536);
+        assert_eq!(handler.processed.get(), 65536);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn simple_endpoints_exchange_packets() {
+        
+        enable_logging();
+
+        let to_2 = RefCell::new(Vec::<Vec<u8>>::new());
+        let to_1 = RefCell::new(Vec::<Vec<u8>>::new());
+        let received_1 = RefCell::new(0);
+        let received_2 = RefCell::new(0);
+
+        let mut endpoint_1 = SimpleEndpoint::new_closure(
+            Config::default(),
+            |_, _, data| to_2.borrow_mut().push(data.to_vec()),
+            |_, _, _| {
+                *received_1.borrow_mut() += 1;
+                1
+            },
+        )
+        .unwrap();
+        let mut endpoint_2 = SimpleEndpoint::new_closure(
             Config::default(),
-            |_, _, _| trace!("enter"),
+            |_, _, data| to_1.borrow_mut().push(data.to_vec()),
             |_, _, _| {
-                trace!("enter");
+                *received_2.borrow_mut() += 1;
                 1
             },
+        )
+        .unwrap();
+
+        // Every fourth packet is large enough to be fragmented.
+        for i in 0..500 {
+            let size = if i % 4 == 0 { 3000 } else { 100 };
+            endpoint_1.send(&vec![i as u8; size]);
+            endpoint_2.send(&vec![i as u8; 100]);
+
+            for packet in to_2.borrow_mut().drain(..) {
+                endpoint_2.recv(&packet);
+            }
+            for packet in to_1.borrow_mut().drain(..) {
+                endpoint_1.recv(&packet);
+            }
+            // The C endpoints start at time 100 and take the absolute time.
+            endpoint_1.update(100.0 + i as f64 * 0.01);
+            endpoint_2.update(100.0 + i as f64 * 0.01);
+        }
+
+        assert_eq!(*received_1.borrow(), 500);
+        assert_eq!(*received_2.borrow(), 500);
+        assert!(!endpoint_1.get_acks().is_empty());
+        assert!(!endpoint_2.get_acks().is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn simple_endpoint_counters() {
+        
+        let to_2 = RefCell::new(Vec::<Vec<u8>>::new());
+        let to_1 = RefCell::new(Vec::<Vec<u8>>::new());
+        let mut endpoint_1 = SimpleEndpoint::new_closure(
+            Config::default(),
+            |_, _, data| to_2.borrow_mut().push(data.to_vec()),
+            |_, _, _| 1,
+        )
+        .unwrap();
+        let mut endpoint_2 = SimpleEndpoint::new_closure(
+            Config::default(),
+            |_, _, data| to_1.borrow_mut().push(data.to_vec()),
+            |_, _, _| 1,
+        )
+        .unwrap();
+        assert_eq!(endpoint_1.counters(), Counters::default());
+
+        // Ten small packets and two that split into three fragments each.
+        for i in 0..12 {
+            let size = if i < 10 { 100 } else { 3000 };
+            endpoint_1.send(&vec![0; size]);
+        }
+        endpoint_1.send(&vec![0; 64 * 1024]);
+        for packet in to_2.borrow_mut().drain(..) {
+            endpoint_2.recv(&packet);
+        }
+        endpoint_2.send(&[0; 100]);
+        for packet in to_1.borrow_mut().drain(..) {
+            endpoint_1.recv(&packet);
+        }
+        endpoint_1.recv(&[0xFF; 2]);
+
+        let counters_1 = endpoint_1.counters();
+        assert_eq!(counters_1.packets_sent, 12);
+        assert_eq!(counters_1.fragments_sent, 6);
+        assert_eq!(counters_1.packets_too_large_to_send, 1);
+        assert_eq!(counters_1.packets_received, 1);
+        assert_eq!(counters_1.packets_acked, 12);
+
+        let counters_2 = endpoint_2.counters();
+        assert_eq!(counters_2.packets_received, 12);
+        assert_eq!(counters_2.fragments_received, 6);
+        assert_eq!(counters_2.packets_sent, 1);
+    }
+
+    struct ChannelHandler {
+        packets: std::sync::mpsc::Sender<Vec<u8>>,
+    }
+    impl EndpointHandler for ChannelHandler {
+        fn on_transmit_packet(&self, _: i32, _: u16, data: &[u8]) {
+            self.packets.send(data.to_vec()).unwrap();
+        }
+        fn on_process_packet(&self, _: i32, _: u16, _: &[u8]) -> i32 {
+            1
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn owned_endpoint_moves_between_threads() {
+        fn assert_send<T: Send>() {}
+        assert_send::<OwnedEndpoint>();
+
+        let (to_2, from_1) = std::sync::mpsc::channel();
+        let (to_1, from_2) = std::sync::mpsc::channel();
+        let reliable = Reliable::new();
+        let mut endpoint_1 = reliable
+            .create_owned_endpoint(
+                Config::default(),
+                Box::new(ChannelHandler { packets: to_2 }),
+            )
+            .unwrap();
+        let mut endpoint_2 = reliable
+            .create_owned_endpoint(
+                Config::default(),
+                Box::new(ChannelHandler { packets: to_1 }),
+            )
+            .unwrap();
+
+        // Each endpoint is driven from its own worker thread, then handed back.
+        let worker_1 = std::thread::spawn(move || {
+            for i in 0..100 {
+                endpoint_1.send(&vec![i as u8; if i % 10 == 0 { 3000 } else { 100 }]);
+                endpoint_1.update(i as f64 * 0.01);
+            }
+            endpoint_1
+        });
+        let worker_2 = std::thread::spawn(move || {
+            for packet in from_1.iter().take(100 + 10 * 2) {
+                endpoint_2.recv(&packet);
+            }
+            endpoint_2.send(&[0; 100]);
+            endpoint_2
+        });
+        let mut endpoint_1 = worker_1.join().unwrap();
+        let endpoint_2 = worker_2.join().unwrap();
+
+        // A single reply acks the latest packet and the 31 before it.
+        endpoint_1.recv(&from_2.recv().unwrap());
+        assert_eq!(endpoint_2.counters().packets_received, 100);
+        assert_eq!(endpoint_1.counters().packets_acked, 32);
+        assert_eq!(endpoint_1.get_acks().len(), 32);
+    }
+
+    #[test]
+    fn allocator_counts_bytes() {
+        // Calls the allocator callbacks directly, without the C library, so this runs under Miri.
+        let mut config: Config = unsafe { std::mem::zeroed() };
+        let allocations = unsafe { create_allocator(&mut config) }.unwrap();
+        let allocate = config.allocate_function.unwrap();
+        let free = config.free_function.unwrap();
+
+        let mut pointers = Vec::new();
+        for size in 1..=64u64 {
+            let pointer = unsafe { allocate(config.allocator_context, size) };
+            assert!(!pointer.is_null());
+            assert_eq!(pointer as usize % ALLOCATION_HEADER, 0);
+            unsafe { std::ptr::write_bytes(pointer as *mut u8, 0xAB, size as usize) };
+            pointers.push(pointer);
+        }
+        let total = (1..=64).sum::<usize>();
+        assert_eq!(
+            allocations.usage(),
+            MemoryUsage {
+                bytes_outstanding: total,
+                peak_bytes: total,
+            }
         );
 
-        endpoint_1.update(10.0);
-        endpoint_2.update(10.0);
+        for pointer in pointers {
+            unsafe { free(config.allocator_context, pointer) };
+        }
+        unsafe { free(config.allocator_context, std::ptr::null_mut()) };
+        assert_eq!(
+            allocations.usage(),
+            MemoryUsage {
+                bytes_outstanding: 0,
+                peak_bytes: total,
+            }
+        );
+    }
+
+    #[test]
+    fn allocator_refuses_config_with_allocator() {
+        let mut config: Config = unsafe { std::mem::zeroed() };
+        let allocations = unsafe { create_allocator(&mut config) }.unwrap();
+        let context = config.allocator_context;
+
+        // A second allocator would have to count through the first one's context.
+        assert!(matches!(
+            unsafe { create_allocator(&mut config) },
+            Err(ReliableError::Config(ConfigError::AllocatorAlreadySet))
+        ));
+        assert_eq!(config.allocator_context, context);
+        drop(allocations);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn simple_endpoint_refuses_config_with_allocator() {
+        let mut config = Config::default();
+        let _allocations = unsafe { create_allocator(&mut config) }.unwrap();
+
+        assert!(matches!(
+            SimpleEndpoint::new_closure(config, |_, _, _| {}, |_, _, _| 1),
+            Err(ReliableError::Config(ConfigError::AllocatorAlreadySet))
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn simple_endpoint_memory_usage() {
+        
+        let to_2 = RefCell::new(Vec::<Vec<u8>>::new());
+        let mut endpoint_1 = SimpleEndpoint::new_closure(
+            Config::default(),
+            |_, _, data| to_2.borrow_mut().push(data.to_vec()),
+            |_, _, _| 1,
+        )
+        .unwrap();
+        let mut endpoint_2 =
+            SimpleEndpoint::new_closure(Config::default(), |_, _, _| {}, |_, _, _| 1).unwrap();
+
+        let created = endpoint_2.memory_usage();
+        assert!(created.bytes_outstanding > 0);
+        assert_eq!(created.peak_bytes, created.bytes_outstanding);
+
+        // Reassembling a fragmented packet holds its data until the last fragment arrives.
+        endpoint_1.send(&[0; 4000]);
+        let fragments: Vec<_> = to_2.borrow_mut().drain(..).collect();
+        endpoint_2.recv(&fragments[0]);
+        assert!(endpoint_2.memory_usage().bytes_outstanding > created.bytes_outstanding);
+        for fragment in &fragments[1..] {
+            endpoint_2.recv(fragment);
+        }
+
+        let usage = endpoint_2.memory_usage();
+        assert_eq!(usage.bytes_outstanding, created.bytes_outstanding);
+        assert!(usage.peak_bytes >= created.bytes_outstanding + 4000);
+    }
+
+    /// The `reliable_*` items `line` of reliable.h declares, in the order bindgen emits them: a
+    /// struct where it is first named, then the function or variable.
+    fn header_declarations<'h>(line: &'h str, seen: &mut Vec<&'h str>) -> Vec<&'h str> {
+        let identifier = |text: &'h str| {
+            let end = text
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(text.len());
+            &text[..end]
+        };
+
+        let mut declared = Vec::new();
+        if let Some(define) = line.strip_prefix("#define ") {
+            // Only integer constants become Rust items; indented defines are platform checks.
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value), None) = (parts.next(), parts.next(), parts.next()) {
+                if name.starts_with("RELIABLE_") && value.parse::<i64>().is_ok() {
+                    declared.push(name);
+                }
+            }
+            return declared;
+        }
+        if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '/') {
+            return declared;
+        }
+
+        for (start, _) in line.match_indices("struct reliable_") {
+            let name = identifier(&line[start + "struct ".len()..]);
+            if !seen.contains(&name) {
+                seen.push(name);
+                declared.push(name);
+            }
+        }
+        if let Some(open) = line.find('(') {
+            // A function is named right before its parameters, a function pointer variable as
+            // `(*name)`.
+            let before = line[..open].trim_end();
+            let start = before
+                .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(0, |i| i + 1);
+            let name = if line[open..].starts_with("(*") {
+                identifier(&line[open + 2..])
+            } else {
+                &before[start..]
+            };
+            if name.starts_with("reliable_") {
+                declared.push(name);
+            }
+        }
+        declared
+    }
+
+    #[test]
+    fn pregenerated_bindings_match_header() {
+        // build.rs uses rust/src/bindings.rs when libclang is missing, so it has to stay in step
+        // with reliable.h. Regenerate it with bindgen when this fails.
+        let mut seen = Vec::new();
+        let header: Vec<&str> = include_str!("../../reliable.h")
+            .lines()
+            .flat_map(|line| header_declarations(line, &mut seen))
+            .collect();
+        let bindings: Vec<&str> = include_str!("bindings.rs")
+            .lines()
+            .filter_map(|line| {
+                let line = line.trim_start();
+                ["pub const ", "pub fn ", "pub static mut ", "pub struct "]
+                    .iter()
+                    .find_map(|prefix| line.strip_prefix(prefix))
+            })
+            .map(|item| {
+                let end = item
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(item.len());
+                &item[..end]
+            })
+            .collect();
+
+        assert!(header.contains(&"reliable_assert_function"));
+        assert_eq!(bindings, header);
+    }
+
+    #[test]
+    fn c_log_levels() {
+        assert_eq!(
+            c_log_level(LevelFilter::Off),
+            RELIABLE_LOG_LEVEL_NONE as i32
+        );
+        assert_eq!(
+            c_log_level(LevelFilter::Warn),
+            RELIABLE_LOG_LEVEL_ERROR as i32
+        );
+        assert_eq!(
+            c_log_level(LevelFilter::Info),
+            RELIABLE_LOG_LEVEL_INFO as i32
+        );
+        assert_eq!(
+            c_log_level(LevelFilter::Trace),
+            RELIABLE_LOG_LEVEL_DEBUG as i32
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[cfg_attr(miri, ignore)]
+    fn c_assert_aborts() {
+        // A failed assert takes the whole process down, so the failing call runs in a copy of
+        // this test in a child process.
+        if std::env::var_os("RELIABLE_C_ASSERT_CHILD").is_some() {
+            let endpoint =
+                SimpleEndpoint::new_closure(Config::default(), |_, _, _| {}, |_, _, _| 1)
+                    .unwrap();
+            // The C library asserts that packets are not empty.
+            unsafe {
+                capi::reliable_endpoint_send_packet(endpoint.handle().ptr(), [].as_ptr(), 0);
+            }
+            return;
+        }
+
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "binding_version::tests::c_assert_aborts", "--nocapture"])
+            .env("RELIABLE_C_ASSERT_CHILD", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(
+                "reliable.io assert failed: ( packet_bytes > 0 ), function \
+                 reliable_endpoint_send_packet"
+            ),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn c_assertion_message() {
+        let assertion = unsafe {
+            CAssertion::from_raw(
+                b"packet_bytes > 0\0".as_ptr() as *const _,
+                b"reliable_endpoint_send_packet\0".as_ptr() as *const _,
+                std::ptr::null(),
+                712,
+            )
+        };
+        assert_eq!(assertion.condition, "packet_bytes > 0");
+        assert_eq!(assertion.file, "");
+        assert_eq!(
+            assertion.to_string(),
+            "reliable.io assert failed: ( packet_bytes > 0 ), function \
+             reliable_endpoint_send_packet, file , line 712"
+        );
     }
 }
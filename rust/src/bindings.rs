@@ -0,0 +1,186 @@
+// Pregenerated bindings for reliable.h, used by build.rs when bindgen cannot load libclang.
+// Regenerate with bindgen when reliable.h changes.
+
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_SENT: u32 = 0;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_RECEIVED: u32 = 1;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_ACKED: u32 = 2;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_STALE: u32 = 3;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_INVALID: u32 = 4;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_TOO_LARGE_TO_SEND: u32 = 5;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_PACKETS_TOO_LARGE_TO_RECEIVE: u32 = 6;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_FRAGMENTS_SENT: u32 = 7;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_FRAGMENTS_RECEIVED: u32 = 8;
+pub const RELIABLE_ENDPOINT_COUNTER_NUM_FRAGMENTS_INVALID: u32 = 9;
+pub const RELIABLE_ENDPOINT_NUM_COUNTERS: u32 = 10;
+pub const RELIABLE_MAX_PACKET_HEADER_BYTES: u32 = 9;
+pub const RELIABLE_FRAGMENT_HEADER_BYTES: u32 = 5;
+pub const RELIABLE_LOG_LEVEL_NONE: u32 = 0;
+pub const RELIABLE_LOG_LEVEL_ERROR: u32 = 1;
+pub const RELIABLE_LOG_LEVEL_INFO: u32 = 2;
+pub const RELIABLE_LOG_LEVEL_DEBUG: u32 = 3;
+pub const RELIABLE_OK: u32 = 1;
+pub const RELIABLE_ERROR: u32 = 0;
+extern "C" {
+    pub fn reliable_init() -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn reliable_term();
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct reliable_config_t {
+    pub name: [::std::os::raw::c_char; 256usize],
+    pub context: *mut ::std::os::raw::c_void,
+    pub index: ::std::os::raw::c_int,
+    pub max_packet_size: ::std::os::raw::c_int,
+    pub fragment_above: ::std::os::raw::c_int,
+    pub max_fragments: ::std::os::raw::c_int,
+    pub fragment_size: ::std::os::raw::c_int,
+    pub ack_buffer_size: ::std::os::raw::c_int,
+    pub sent_packets_buffer_size: ::std::os::raw::c_int,
+    pub received_packets_buffer_size: ::std::os::raw::c_int,
+    pub fragment_reassembly_buffer_size: ::std::os::raw::c_int,
+    pub rtt_smoothing_factor: f32,
+    pub packet_loss_smoothing_factor: f32,
+    pub bandwidth_smoothing_factor: f32,
+    pub packet_header_size: ::std::os::raw::c_int,
+    pub transmit_packet_function: ::std::option::Option<
+        unsafe extern "C" fn(
+            arg1: *mut ::std::os::raw::c_void,
+            arg2: ::std::os::raw::c_int,
+            arg3: u16,
+            arg4: *const u8,
+            arg5: ::std::os::raw::c_int,
+        ),
+    >,
+    pub process_packet_function: ::std::option::Option<
+        unsafe extern "C" fn(
+            arg1: *mut ::std::os::raw::c_void,
+            arg2: ::std::os::raw::c_int,
+            arg3: u16,
+            arg4: *const u8,
+            arg5: ::std::os::raw::c_int,
+        ) -> ::std::os::raw::c_int,
+    >,
+    pub allocator_context: *mut ::std::os::raw::c_void,
+    pub allocate_function: ::std::option::Option<
+        unsafe extern "C" fn(
+            arg1: *mut ::std::os::raw::c_void,
+            arg2: u64,
+        ) -> *mut ::std::os::raw::c_void,
+    >,
+    pub free_function: ::std::option::Option<
+        unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void, arg2: *mut ::std::os::raw::c_void),
+    >,
+}
+extern "C" {
+    pub fn reliable_default_config(config: *mut reliable_config_t);
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct reliable_endpoint_t {
+    _unused: [u8; 0],
+}
+extern "C" {
+    pub fn reliable_endpoint_create(
+        config: *const reliable_config_t,
+        time: f64,
+    ) -> *mut reliable_endpoint_t;
+}
+extern "C" {
+    pub fn reliable_endpoint_next_packet_sequence(endpoint: *const reliable_endpoint_t) -> u16;
+}
+extern "C" {
+    pub fn reliable_endpoint_send_packet(
+        endpoint: *mut reliable_endpoint_t,
+        packet_data: *const u8,
+        packet_bytes: ::std::os::raw::c_int,
+    );
+}
+extern "C" {
+    pub fn reliable_endpoint_receive_packet(
+        endpoint: *mut reliable_endpoint_t,
+        packet_data: *const u8,
+        packet_bytes: ::std::os::raw::c_int,
+    );
+}
+extern "C" {
+    pub fn reliable_endpoint_free_packet(
+        endpoint: *mut reliable_endpoint_t,
+        packet: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn reliable_endpoint_get_acks(
+        endpoint: *const reliable_endpoint_t,
+        num_acks: *mut ::std::os::raw::c_int,
+    ) -> *mut u16;
+}
+extern "C" {
+    pub fn reliable_endpoint_clear_acks(endpoint: *mut reliable_endpoint_t);
+}
+extern "C" {
+    pub fn reliable_endpoint_reset(endpoint: *mut reliable_endpoint_t);
+}
+extern "C" {
+    pub fn reliable_endpoint_update(endpoint: *mut reliable_endpoint_t, time: f64);
+}
+extern "C" {
+    pub fn reliable_endpoint_rtt(endpoint: *const reliable_endpoint_t) -> f32;
+}
+extern "C" {
+    pub fn reliable_endpoint_packet_loss(endpoint: *const reliable_endpoint_t) -> f32;
+}
+extern "C" {
+    pub fn reliable_endpoint_bandwidth(
+        endpoint: *const reliable_endpoint_t,
+        sent_bandwidth_kbps: *mut f32,
+        received_bandwidth_kbps: *mut f32,
+        acked_bandwidth_kpbs: *mut f32,
+    );
+}
+extern "C" {
+    pub fn reliable_endpoint_counters(endpoint: *const reliable_endpoint_t) -> *const u64;
+}
+extern "C" {
+    pub fn reliable_endpoint_destroy(endpoint: *mut reliable_endpoint_t);
+}
+extern "C" {
+    pub fn reliable_log_level(level: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn reliable_set_printf_function(
+        function: ::std::option::Option<
+            unsafe extern "C" fn(arg1: *const ::std::os::raw::c_char, ...) -> ::std::os::raw::c_int,
+        >,
+    );
+}
+extern "C" {
+    pub fn reliable_set_log_function(
+        function: ::std::option::Option<
+            unsafe extern "C" fn(arg1: ::std::os::raw::c_int, arg2: *const ::std::os::raw::c_char),
+        >,
+    );
+}
+extern "C" {
+    pub static mut reliable_assert_function: ::std::option::Option<
+        unsafe extern "C" fn(
+            arg1: *const ::std::os::raw::c_char,
+            arg2: *const ::std::os::raw::c_char,
+            file: *const ::std::os::raw::c_char,
+            line: ::std::os::raw::c_int,
+        ),
+    >;
+}
+extern "C" {
+    pub fn reliable_set_assert_function(
+        function: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: *const ::std::os::raw::c_char,
+                arg2: *const ::std::os::raw::c_char,
+                arg3: *const ::std::os::raw::c_char,
+                arg4: ::std::os::raw::c_int,
+            ),
+        >,
+    );
+}
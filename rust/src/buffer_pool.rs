@@ -0,0 +1,56 @@
+use alloc::vec::Vec;
+
+/// A free list of packet buffers, so steady state sends and receives reuse allocations.
+///
+/// Buffers handed out by `acquire` are empty but keep whatever capacity they grew to. When the
+/// pool is empty a fresh buffer is allocated and counted as a miss; buffers released while the
+/// pool is already full are dropped.
+pub struct BufferPool {
+    buffers: Vec<Vec<u8>>,
+    max_buffers: usize,
+    buffer_capacity: usize,
+    misses: u64,
+}
+
+impl BufferPool {
+    pub fn with_capacity(max_buffers: usize, buffer_capacity: usize) -> Self {
+        let mut buffers = Vec::with_capacity(max_buffers);
+        buffers.resize_with(max_buffers, || Vec::with_capacity(buffer_capacity));
+
+        Self {
+            buffers,
+            max_buffers,
+            buffer_capacity,
+            misses: 0,
+        }
+    }
+
+    pub fn acquire(&mut self) -> Vec<u8> {
+        if let Some(buffer) = self.buffers.pop() {
+            buffer
+        } else {
+            self.misses += 1;
+            Vec::with_capacity(self.buffer_capacity)
+        }
+    }
+
+    pub fn release(&mut self, mut buffer: Vec<u8>) {
+        if self.buffers.len() < self.max_buffers && buffer.capacity() > 0 {
+            buffer.clear();
+            self.buffers.push(buffer);
+        }
+    }
+
+    /// Number of `acquire` calls that had to allocate because the pool was empty.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn available(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_buffers
+    }
+}
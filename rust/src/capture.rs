@@ -0,0 +1,352 @@
+//! Taps on the datagrams an `Endpoint` sends and receives, and a pcap writer to record them.
+//!
+//! `PcapWriter` frames each datagram in synthetic IPv4 and UDP headers, so captures open in
+//! standard tools with the endpoint clock as timestamps. `PcapReader` reads them back, along
+//! with UDP traffic captured off the network, for `replay`.
+
+/// Which way a tapped datagram was going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+}
+
+/// Receives every datagram an `Endpoint` sends or receives, see `Endpoint::set_tap`.
+///
+/// `time` is the endpoint clock in seconds. Incoming datagrams are tapped before they are
+/// validated, so malformed ones are seen too.
+pub trait PacketTap {
+    fn on_datagram(&mut self, time: f64, direction: Direction, datagram: &[u8]);
+}
+
+/// A datagram read back from a capture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedDatagram {
+    /// Capture timestamp in seconds.
+    pub time: f64,
+    pub direction: Direction,
+    pub datagram: alloc::vec::Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+pub use self::pcap::{PcapReader, PcapWriter};
+
+#[cfg(feature = "std")]
+mod pcap {
+    use super::{CapturedDatagram, Direction, PacketTap};
+    use log::*;
+    use std::convert::TryFrom;
+    use std::io::{self, Read, Write};
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    const MAGIC: u32 = 0xA1B2_C3D4;
+    const MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+    const SNAPLEN: u32 = 65535;
+    /// Raw IP, the record starts at the IPv4 header.
+    const LINKTYPE_RAW: u32 = 101;
+    const LINKTYPE_ETHERNET: u32 = 1;
+    const LINKTYPE_IPV4: u32 = 228;
+    const ETHERNET_HEADER_BYTES: usize = 14;
+    const ETHERTYPE_IPV4: u16 = 0x0800;
+    const IPV4_HEADER_BYTES: usize = 20;
+    const UDP_HEADER_BYTES: usize = 8;
+    const IPPROTO_UDP: u8 = 17;
+
+    /// Writes tapped datagrams to a pcap stream as UDP over IPv4 between a local and a remote
+    /// address.
+    pub struct PcapWriter<W: Write> {
+        writer: W,
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+        ip_id: u16,
+    }
+
+    impl PcapWriter<io::BufWriter<std::fs::File>> {
+        /// Creates a pcap file at `path`, replacing any file already there.
+        pub fn create<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+            Self::new(io::BufWriter::new(std::fs::File::create(path)?))
+        }
+    }
+
+    impl<W: Write> PcapWriter<W> {
+        /// Writes the pcap file header. Datagrams go between 10.0.0.1:40000 locally and
+        /// 10.0.0.2:40000 remotely unless changed with `with_addresses`.
+        pub fn new(mut writer: W) -> io::Result<Self> {
+            writer.write_all(&MAGIC.to_le_bytes())?;
+            writer.write_all(&2u16.to_le_bytes())?;
+            writer.write_all(&4u16.to_le_bytes())?;
+            writer.write_all(&0i32.to_le_bytes())?;
+            writer.write_all(&0u32.to_le_bytes())?;
+            writer.write_all(&SNAPLEN.to_le_bytes())?;
+            writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+
+            Ok(Self {
+                writer,
+                local: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 40000),
+                remote: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 40000),
+                ip_id: 0,
+            })
+        }
+
+        #[must_use]
+        pub fn with_addresses(mut self, local: SocketAddrV4, remote: SocketAddrV4) -> Self {
+            self.local = local;
+            self.remote = remote;
+            self
+        }
+
+        /// Writes one datagram as a pcap record stamped with `time`, in seconds.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        pub fn write_datagram(
+            &mut self,
+            time: f64,
+            direction: Direction,
+            datagram: &[u8],
+        ) -> io::Result<()> {
+            let (source, destination) = match direction {
+                Direction::Outgoing => (self.local, self.remote),
+                Direction::Incoming => (self.remote, self.local),
+            };
+
+            let udp_length = UDP_HEADER_BYTES + datagram.len();
+            let ip_length = IPV4_HEADER_BYTES + udp_length;
+            let mut frame = Vec::with_capacity(ip_length);
+
+            let mut ip_header = [0u8; IPV4_HEADER_BYTES];
+            ip_header[0] = 0x45;
+            ip_header[2..4].copy_from_slice(&saturate(ip_length).to_be_bytes());
+            ip_header[4..6].copy_from_slice(&self.ip_id.to_be_bytes());
+            ip_header[8] = 64;
+            ip_header[9] = IPPROTO_UDP;
+            ip_header[12..16].copy_from_slice(&source.ip().octets());
+            ip_header[16..20].copy_from_slice(&destination.ip().octets());
+            let checksum = ipv4_checksum(&ip_header);
+            ip_header[10..12].copy_from_slice(&checksum.to_be_bytes());
+            self.ip_id = self.ip_id.wrapping_add(1);
+            frame.extend_from_slice(&ip_header);
+
+            // A zero UDP checksum means none was computed, which IPv4 allows.
+            frame.extend_from_slice(&source.port().to_be_bytes());
+            frame.extend_from_slice(&destination.port().to_be_bytes());
+            frame.extend_from_slice(&saturate(udp_length).to_be_bytes());
+            frame.extend_from_slice(&0u16.to_be_bytes());
+            frame.extend_from_slice(datagram);
+
+            let micros = (time.max(0.0) * 1_000_000.0).round() as u64;
+            let seconds = u32::try_from(micros / 1_000_000).unwrap_or(u32::MAX);
+            let micros = (micros % 1_000_000) as u32;
+            let original_length = u32::try_from(frame.len()).unwrap_or(u32::MAX);
+            let captured = frame.len().min(SNAPLEN as usize);
+
+            self.writer.write_all(&seconds.to_le_bytes())?;
+            self.writer.write_all(&micros.to_le_bytes())?;
+            self.writer.write_all(&(captured as u32).to_le_bytes())?;
+            self.writer.write_all(&original_length.to_le_bytes())?;
+            self.writer.write_all(&frame[..captured])
+        }
+
+        pub fn flush(&mut self) -> io::Result<()> {
+            self.writer.flush()
+        }
+
+        pub fn into_inner(self) -> W {
+            self.writer
+        }
+    }
+
+    impl<W: Write> PacketTap for PcapWriter<W> {
+        fn on_datagram(&mut self, time: f64, direction: Direction, datagram: &[u8]) {
+            if let Err(e) = self.write_datagram(time, direction, datagram) {
+                error!("Failed to write datagram to capture: {e}");
+            }
+        }
+    }
+
+    /// Reads UDP over IPv4 datagrams from a pcap stream, such as one written by `PcapWriter`.
+    ///
+    /// Datagrams to the local address are `Incoming` and datagrams from it are `Outgoing`; any
+    /// other traffic in the capture is skipped unless `with_all_datagrams` is set. Raw IP, IPv4
+    /// and Ethernet link types are read.
+    pub struct PcapReader<R: Read> {
+        reader: R,
+        local: SocketAddrV4,
+        all_datagrams: bool,
+        big_endian: bool,
+        nanos: bool,
+        snaplen: u32,
+        linktype: u32,
+    }
+
+    impl PcapReader<io::BufReader<std::fs::File>> {
+        pub fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+            Self::new(io::BufReader::new(std::fs::File::open(path)?))
+        }
+    }
+
+    impl<R: Read> PcapReader<R> {
+        /// Reads the pcap file header. The local address is 10.0.0.1:40000, as for
+        /// `PcapWriter`, unless changed with `with_local_address`.
+        pub fn new(mut reader: R) -> io::Result<Self> {
+            let mut header = [0u8; 24];
+            reader.read_exact(&mut header)?;
+            let magic = [header[0], header[1], header[2], header[3]];
+            let (big_endian, nanos) = match u32::from_le_bytes(magic) {
+                MAGIC => (false, false),
+                MAGIC_NANOS => (false, true),
+                _ => match u32::from_be_bytes(magic) {
+                    MAGIC => (true, false),
+                    MAGIC_NANOS => (true, true),
+                    _ => return Err(invalid_data("not a pcap file")),
+                },
+            };
+
+            let mut capture = Self {
+                reader,
+                local: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 40000),
+                all_datagrams: false,
+                big_endian,
+                nanos,
+                snaplen: 0,
+                linktype: 0,
+            };
+            capture.snaplen = capture.u32_at(&header, 16);
+            capture.linktype = capture.u32_at(&header, 20);
+            match capture.linktype {
+                LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_ETHERNET => Ok(capture),
+                linktype => Err(invalid_data(&format!("unsupported link type {linktype}"))),
+            }
+        }
+
+        #[must_use]
+        pub fn with_local_address(mut self, local: SocketAddrV4) -> Self {
+            self.local = local;
+            self
+        }
+
+        /// Reads every UDP datagram in the capture, taking those not from the local address as
+        /// `Incoming`.
+        #[must_use]
+        pub fn with_all_datagrams(mut self) -> Self {
+            self.all_datagrams = true;
+            self
+        }
+
+        /// Reads up to the next datagram to or from the local address, or `None` at the end of
+        /// the capture.
+        pub fn read_datagram(&mut self) -> io::Result<Option<CapturedDatagram>> {
+            loop {
+                let mut header = [0u8; 16];
+                match self.reader.read_exact(&mut header) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(e) => return Err(e),
+                }
+                let seconds = self.u32_at(&header, 0);
+                let fraction = self.u32_at(&header, 4);
+                let captured = self.u32_at(&header, 8);
+                // The length comes from the file, so it is checked before it is allocated.
+                if captured > self.snaplen.min(SNAPLEN) {
+                    return Err(invalid_data(&format!(
+                        "record of {captured} bytes is longer than the snapshot length {}",
+                        self.snaplen.min(SNAPLEN)
+                    )));
+                }
+                let mut frame = vec![0u8; captured as usize];
+                self.reader.read_exact(&mut frame)?;
+
+                let fraction = if self.nanos {
+                    f64::from(fraction) / 1e9
+                } else {
+                    f64::from(fraction) / 1e6
+                };
+                let time = f64::from(seconds) + fraction;
+                if let Some(datagram) = self.udp_datagram(time, &frame) {
+                    return Ok(Some(datagram));
+                }
+            }
+        }
+
+        fn udp_datagram(&self, time: f64, frame: &[u8]) -> Option<CapturedDatagram> {
+            let ip = if self.linktype == LINKTYPE_ETHERNET {
+                let ethertype = frame.get(12..ETHERNET_HEADER_BYTES)?;
+                if u16::from_be_bytes([ethertype[0], ethertype[1]]) != ETHERTYPE_IPV4 {
+                    return None;
+                }
+                &frame[ETHERNET_HEADER_BYTES..]
+            } else {
+                frame
+            };
+
+            let header_length = usize::from(ip.first()? & 0x0F) * 4;
+            if ip[0] >> 4 != 4 || header_length < IPV4_HEADER_BYTES || ip.len() < header_length {
+                return None;
+            }
+            let total_length = usize::from(u16::from_be_bytes([ip[2], ip[3]]));
+            let fragment = u16::from_be_bytes([ip[6], ip[7]]);
+            if ip[9] != IPPROTO_UDP || fragment & 0x3FFF != 0 {
+                return None;
+            }
+            let address = |at: usize| Ipv4Addr::new(ip[at], ip[at + 1], ip[at + 2], ip[at + 3]);
+            let udp = &ip[header_length..total_length.clamp(header_length, ip.len())];
+            let port = |at: usize| u16::from_be_bytes([udp[at], udp[at + 1]]);
+            if udp.len() < UDP_HEADER_BYTES {
+                return None;
+            }
+            let source = SocketAddrV4::new(address(12), port(0));
+            let destination = SocketAddrV4::new(address(16), port(2));
+            let udp_length = usize::from(port(4)).clamp(UDP_HEADER_BYTES, udp.len());
+
+            let direction = if destination == self.local {
+                Direction::Incoming
+            } else if source == self.local {
+                Direction::Outgoing
+            } else if self.all_datagrams {
+                Direction::Incoming
+            } else {
+                return None;
+            };
+            Some(CapturedDatagram {
+                time,
+                direction,
+                datagram: udp[UDP_HEADER_BYTES..udp_length].to_vec(),
+            })
+        }
+
+        fn u32_at(&self, bytes: &[u8], at: usize) -> u32 {
+            let word = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+            if self.big_endian {
+                u32::from_be_bytes(word)
+            } else {
+                u32::from_le_bytes(word)
+            }
+        }
+    }
+
+    impl<R: Read> Iterator for PcapReader<R> {
+        type Item = io::Result<CapturedDatagram>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.read_datagram().transpose()
+        }
+    }
+
+    fn invalid_data(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+
+    fn saturate(length: usize) -> u16 {
+        u16::try_from(length).unwrap_or(u16::MAX)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn ipv4_checksum(header: &[u8]) -> u16 {
+        let mut sum: u32 = header
+            .chunks(2)
+            .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+            .sum();
+        while sum > 0xFFFF {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+}
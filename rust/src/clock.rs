@@ -0,0 +1,89 @@
+//! Where an `Endpoint` gets its time from.
+//!
+//! Times are `Duration`s since the clock's own epoch, so RTT and bandwidth keep their precision
+//! however long the endpoint runs.
+
+#[cfg(target_has_atomic = "64")]
+use alloc::sync::Arc;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+/// A monotonic source of time for an `Endpoint`, see `Endpoint::with_clock`.
+pub trait Clock {
+    /// Time since the clock's epoch. Must never go backwards.
+    fn now(&self) -> Duration;
+}
+
+/// A clock that only moves when told to, for simulations, replays and tests.
+///
+/// Clones share the same time, so one kept outside an endpoint drives the endpoint's copy. Only
+/// available on targets with 64-bit atomics.
+#[cfg(target_has_atomic = "64")]
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl VirtualClock {
+    pub fn new(start: Duration) -> Self {
+        let clock = Self::default();
+        clock.set(start);
+        clock
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.set(self.now() + by);
+    }
+
+    /// Moves the clock to `time`, or leaves it where it is if `time` is behind it.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn set(&self, time: Duration) {
+        let nanos = time.as_nanos().min(u128::from(u64::MAX)) as u64;
+        self.nanos.fetch_max(nanos, Ordering::AcqRel);
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Acquire))
+    }
+}
+
+/// Wall clock time from `std::time::Instant`, counted from when the clock was made.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct InstantClock {
+    epoch: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl InstantClock {
+    pub fn new() -> Self {
+        Self {
+            epoch: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for InstantClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for InstantClock {
+    fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+}
+
+/// Converts seconds as `Endpoint::new` and `Endpoint::update` take them, treating negative and
+/// non-finite times as zero.
+pub(crate) fn from_secs(time: f64) -> Duration {
+    Duration::try_from_secs_f64(time).unwrap_or_default()
+}
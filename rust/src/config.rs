@@ -0,0 +1,269 @@
+use crate::{AckFormat, AppHeaderFraming, ConfigError, FragmentFormat};
+use alloc::string::{String, ToString};
+
+/// Size of the application header registered on an endpoint.
+///
+/// `Fixed` headers are written as-is and must always be exactly that many bytes, `Variable`
+/// headers are prefixed with a single length byte and may be up to 255 bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AppHeaderSize {
+    Fixed(usize),
+    Variable,
+}
+
+impl AppHeaderSize {
+    pub fn framing(self) -> AppHeaderFraming {
+        match self {
+            AppHeaderSize::Fixed(_) => AppHeaderFraming::Fixed,
+            AppHeaderSize::Variable => AppHeaderFraming::LengthPrefixed,
+        }
+    }
+}
+
+/// With the `serde` feature enabled, fields missing from a serialized config are taken from
+/// `EndpointConfig::default()`.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct EndpointConfig {
+    pub name: String,
+    pub index: i32,
+    pub max_packet_size: usize,
+    pub fragment_above: usize,
+    /// At most `fragment_format.max_fragments()`, so 255 with the narrow format.
+    pub max_fragments: u32,
+    pub fragment_size: usize,
+    pub fragment_format: FragmentFormat,
+    pub app_header: Option<AppHeaderSize>,
+    pub ack_format: AckFormat,
+    pub ack_buffer_size: usize,
+    pub sent_packets_buffer_size: usize,
+    pub received_packets_buffer_size: usize,
+    pub fragment_reassembly_buffer_size: usize,
+    pub buffer_pool_size: usize,
+    pub rtt_smoothing_factor: f32,
+    pub packet_loss_smoothing_factor: f32,
+    pub bandwidth_smoothing_factor: f32,
+    pub packet_header_size: usize,
+}
+
+impl EndpointConfig {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn builder(name: &str) -> EndpointConfigBuilder {
+        EndpointConfigBuilder::new(name)
+    }
+
+    /// Checks the invariants the `Endpoint` relies on. Called by `EndpointConfigBuilder::build`
+    /// and `Endpoint::new`, so configs assembled by hand are held to the same rules.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let sizes = [
+            ("max_packet_size", self.max_packet_size),
+            ("fragment_size", self.fragment_size),
+            ("ack_buffer_size", self.ack_buffer_size),
+            ("sent_packets_buffer_size", self.sent_packets_buffer_size),
+            ("received_packets_buffer_size", self.received_packets_buffer_size),
+            ("fragment_reassembly_buffer_size", self.fragment_reassembly_buffer_size),
+        ];
+        for (field, size) in &sizes {
+            if *size == 0 {
+                return Err(ConfigError::ZeroSize(field));
+            }
+        }
+
+        if self.fragment_size > self.max_packet_size {
+            return Err(ConfigError::FragmentSizeExceedsMaxPacketSize {
+                fragment_size: self.fragment_size,
+                max_packet_size: self.max_packet_size,
+            });
+        }
+
+        let max_fragments = self.max_fragments as usize;
+        if max_fragments > self.fragment_format.max_fragments() {
+            return Err(ConfigError::TooManyFragmentsForFormat {
+                max_fragments: self.max_fragments,
+                format: self.fragment_format,
+            });
+        }
+        if max_fragments * self.fragment_size < self.max_packet_size {
+            return Err(ConfigError::TooFewFragments {
+                max_fragments: self.max_fragments,
+                fragment_size: self.fragment_size,
+                max_packet_size: self.max_packet_size,
+            });
+        }
+
+        let factors = [
+            ("rtt_smoothing_factor", self.rtt_smoothing_factor),
+            ("packet_loss_smoothing_factor", self.packet_loss_smoothing_factor),
+            ("bandwidth_smoothing_factor", self.bandwidth_smoothing_factor),
+        ];
+        for (field, factor) in &factors {
+            if !(*factor > 0.0 && *factor <= 1.0) {
+                return Err(ConfigError::SmoothingFactorOutOfRange(field, *factor));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl EndpointConfig {
+    /// Parses a TOML config, filling missing fields from the defaults, and validates it.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(config: &str) -> Result<Self, ConfigError> {
+        let config: Self =
+            toml::from_str(config).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses a JSON config, filling missing fields from the defaults, and validates it.
+    #[cfg(feature = "json")]
+    pub fn from_json_str(config: &str) -> Result<Self, ConfigError> {
+        let config: Self =
+            serde_json::from_str(config).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads a config file, picking the format from its `.toml` or `.json` extension.
+    #[cfg(any(feature = "toml", feature = "json"))]
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(e.to_string()))?;
+
+        match path.extension().and_then(std::ffi::OsStr::to_str) {
+            #[cfg(feature = "toml")]
+            Some("toml") => Self::from_toml_str(&contents),
+            #[cfg(feature = "json")]
+            Some("json") => Self::from_json_str(&contents),
+            _ => Err(ConfigError::Parse(format!(
+                "unsupported config file format: {}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            index: 1,
+            max_packet_size: 16 * 1024,
+            fragment_above: 1024,
+            max_fragments: 16,
+            fragment_size: 1024,
+            fragment_format: FragmentFormat::Narrow,
+            app_header: None,
+            ack_format: AckFormat::Narrow,
+            ack_buffer_size: 256,
+            sent_packets_buffer_size: 256,
+            received_packets_buffer_size: 256,
+            fragment_reassembly_buffer_size: 64,
+            buffer_pool_size: 64,
+            rtt_smoothing_factor: 0.0025,
+            packet_loss_smoothing_factor: 0.1,
+            bandwidth_smoothing_factor: 0.1,
+            packet_header_size: 28,
+        }
+    }
+}
+
+/// Builds an `EndpointConfig` starting from the defaults, validating it on `build`.
+#[must_use]
+pub struct EndpointConfigBuilder {
+    config: EndpointConfig,
+}
+
+impl EndpointConfigBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            config: EndpointConfig::new(name),
+        }
+    }
+
+    pub fn index(mut self, index: i32) -> Self {
+        self.config.index = index;
+        self
+    }
+    pub fn max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.config.max_packet_size = max_packet_size;
+        self
+    }
+    pub fn fragment_above(mut self, fragment_above: usize) -> Self {
+        self.config.fragment_above = fragment_above;
+        self
+    }
+    pub fn max_fragments(mut self, max_fragments: u32) -> Self {
+        self.config.max_fragments = max_fragments;
+        self
+    }
+    pub fn fragment_size(mut self, fragment_size: usize) -> Self {
+        self.config.fragment_size = fragment_size;
+        self
+    }
+    pub fn fragment_format(mut self, fragment_format: FragmentFormat) -> Self {
+        self.config.fragment_format = fragment_format;
+        self
+    }
+    pub fn app_header(mut self, app_header: AppHeaderSize) -> Self {
+        self.config.app_header = Some(app_header);
+        self
+    }
+    pub fn ack_format(mut self, ack_format: AckFormat) -> Self {
+        self.config.ack_format = ack_format;
+        self
+    }
+    pub fn ack_buffer_size(mut self, ack_buffer_size: usize) -> Self {
+        self.config.ack_buffer_size = ack_buffer_size;
+        self
+    }
+    pub fn sent_packets_buffer_size(mut self, sent_packets_buffer_size: usize) -> Self {
+        self.config.sent_packets_buffer_size = sent_packets_buffer_size;
+        self
+    }
+    pub fn received_packets_buffer_size(mut self, received_packets_buffer_size: usize) -> Self {
+        self.config.received_packets_buffer_size = received_packets_buffer_size;
+        self
+    }
+    pub fn fragment_reassembly_buffer_size(mut self, fragment_reassembly_buffer_size: usize) -> Self {
+        self.config.fragment_reassembly_buffer_size = fragment_reassembly_buffer_size;
+        self
+    }
+    pub fn buffer_pool_size(mut self, buffer_pool_size: usize) -> Self {
+        self.config.buffer_pool_size = buffer_pool_size;
+        self
+    }
+    pub fn rtt_smoothing_factor(mut self, rtt_smoothing_factor: f32) -> Self {
+        self.config.rtt_smoothing_factor = rtt_smoothing_factor;
+        self
+    }
+    pub fn packet_loss_smoothing_factor(mut self, packet_loss_smoothing_factor: f32) -> Self {
+        self.config.packet_loss_smoothing_factor = packet_loss_smoothing_factor;
+        self
+    }
+    pub fn bandwidth_smoothing_factor(mut self, bandwidth_smoothing_factor: f32) -> Self {
+        self.config.bandwidth_smoothing_factor = bandwidth_smoothing_factor;
+        self
+    }
+    pub fn packet_header_size(mut self, packet_header_size: usize) -> Self {
+        self.config.packet_header_size = packet_header_size;
+        self
+    }
+
+    pub fn build(self) -> Result<EndpointConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
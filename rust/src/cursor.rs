@@ -0,0 +1,111 @@
+//! Byte cursor used by `HeaderParser`.
+//!
+//! With the `std` feature this is simply `std::io::Cursor`. Without it a minimal cursor over a
+//! byte slice provides the handful of `byteorder`-style reads and writes the headers need.
+
+#[cfg(feature = "std")]
+pub use std::io::Cursor;
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::Cursor;
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use crate::ReliableError;
+    use byteorder::ByteOrder;
+
+    #[derive(Debug, Default, Clone)]
+    pub struct Cursor<T> {
+        inner: T,
+        pos: u64,
+    }
+
+    impl<T> Cursor<T> {
+        pub fn new(inner: T) -> Self {
+            Self { inner, pos: 0 }
+        }
+
+        pub fn into_inner(self) -> T {
+            self.inner
+        }
+
+        pub fn get_ref(&self) -> &T {
+            &self.inner
+        }
+
+        pub fn get_mut(&mut self) -> &mut T {
+            &mut self.inner
+        }
+
+        pub fn position(&self) -> u64 {
+            self.pos
+        }
+
+        pub fn set_position(&mut self, pos: u64) {
+            self.pos = pos;
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    impl<T: AsRef<[u8]>> Cursor<T> {
+        fn take(&mut self, len: usize) -> Result<&[u8], ReliableError> {
+            let data = self.inner.as_ref();
+            let start = (self.pos as usize).min(data.len());
+            if data.len() - start < len {
+                return Err(ReliableError::PacketTooSmall);
+            }
+            self.pos += len as u64;
+            Ok(&data[start..start + len])
+        }
+
+        pub fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReliableError> {
+            let len = buf.len();
+            buf.copy_from_slice(self.take(len)?);
+            Ok(())
+        }
+
+        pub fn read_u8(&mut self) -> Result<u8, ReliableError> {
+            Ok(self.take(1)?[0])
+        }
+
+        pub fn read_u16<B: ByteOrder>(&mut self) -> Result<u16, ReliableError> {
+            Ok(B::read_u16(self.take(2)?))
+        }
+
+        pub fn read_u32<B: ByteOrder>(&mut self) -> Result<u32, ReliableError> {
+            Ok(B::read_u32(self.take(4)?))
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    impl Cursor<&mut [u8]> {
+        fn take_mut(&mut self, len: usize) -> Result<&mut [u8], ReliableError> {
+            let start = (self.pos as usize).min(self.inner.len());
+            if self.inner.len() - start < len {
+                return Err(ReliableError::ExceededMaxPacketSize);
+            }
+            self.pos += len as u64;
+            Ok(&mut self.inner[start..start + len])
+        }
+
+        pub fn write_all(&mut self, buf: &[u8]) -> Result<(), ReliableError> {
+            self.take_mut(buf.len())?.copy_from_slice(buf);
+            Ok(())
+        }
+
+        pub fn write_u8(&mut self, n: u8) -> Result<(), ReliableError> {
+            self.take_mut(1)?[0] = n;
+            Ok(())
+        }
+
+        pub fn write_u16<B: ByteOrder>(&mut self, n: u16) -> Result<(), ReliableError> {
+            B::write_u16(self.take_mut(2)?, n);
+            Ok(())
+        }
+
+        pub fn write_u32<B: ByteOrder>(&mut self, n: u32) -> Result<(), ReliableError> {
+            B::write_u32(self.take_mut(4)?, n);
+            Ok(())
+        }
+    }
+}
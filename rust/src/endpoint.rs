@@ -0,0 +1,2082 @@
+//! The pure-Rust `Endpoint`: sending and fragmenting packets, reassembling and acking the ones
+//! received, and the RTT, packet loss and bandwidth estimates kept from them.
+
+mod replay;
+mod snapshot;
+
+pub use self::replay::{replay, ReplayEvent, ReplayReport};
+
+use crate::{clock, sequence_buffer};
+use crate::{
+    AckFormat, AppHeaderFraming, AppHeaderSize, BufferPool, Clock, ConfigError, Counters, Cursor,
+    Direction, EndpointConfig, EndpointMetrics, FragmentFormat, FragmentHeader, Header,
+    PacketHeader, PacketTap, ReliableEndpoint, ReliableError, SequenceBuffer,
+    RELIABLE_FRAGMENT_HEADER_BYTES, RELIABLE_MAX_PACKET_HEADER_BYTES,
+    RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES, RELIABLE_WIDE_FRAGMENT_HEADER_BYTES,
+};
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use byteorder::ReadBytesExt;
+use core::convert::TryFrom;
+use core::num::Wrapping;
+use core::time::Duration;
+use log::*;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+/// Emits a `tracing` event at `$level`, or nothing at all without the `tracing` feature.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        tracing::$level!($($arg)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {};
+}
+
+/// Enters a `tracing` debug span until the end of the enclosing block, or does nothing without
+/// the `tracing` feature.
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($arg:tt)+) => {
+        let _span = tracing::debug_span!($($arg)+).entered();
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($arg:tt)+) => {};
+}
+
+/*
+struct reliable_fragment_reassembly_data_t
+{
+    uint16_t sequence;
+    uint16_t ack;
+    uint32_t ack_bits;
+    int num_fragments_received;
+    int num_fragments_total;
+    uint8_t * packet_data;
+    int packet_bytes;
+    int packet_header_bytes;
+    uint8_t fragment_received[256];
+};
+
+*/
+
+#[derive(Clone, Default)]
+struct ReassemblyData {
+    sequence: u16,
+    ack: u16,
+    ack_bits: u64,
+    num_fragments_received: usize,
+    num_fragments_total: usize,
+    buffer: Vec<u8>,
+    packet_bytes: usize,
+    /// One bit per fragment, drawn from the buffer pool like `buffer`.
+    fragments_received: Vec<u8>,
+    header_size: usize,
+}
+
+impl ReassemblyData {
+    pub fn new(
+        sequence: u16,
+        num_fragments_total: usize,
+        fragment_size: usize,
+        mut buffer: Vec<u8>,
+        mut fragments_received: Vec<u8>,
+    ) -> Self {
+        buffer.resize(
+            RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES + num_fragments_total * fragment_size,
+            0,
+        );
+        fragments_received.resize(num_fragments_total.div_ceil(8), 0);
+        Self {
+            sequence,
+            ack: 0,
+            ack_bits: 0,
+            num_fragments_received: 0,
+            num_fragments_total,
+            buffer,
+            packet_bytes: 0,
+            fragments_received,
+            header_size: 0,
+        }
+    }
+
+    /// Hands the reassembly's buffers back to `pool`.
+    fn release(self, pool: &mut BufferPool) {
+        pool.release(self.buffer);
+        pool.release(self.fragments_received);
+    }
+
+    fn is_received(&self, id: usize) -> bool {
+        self.fragments_received[id / 8] & (1 << (id % 8)) != 0
+    }
+
+    fn mark_received(&mut self, id: usize) {
+        self.fragments_received[id / 8] |= 1 << (id % 8);
+        self.num_fragments_received += 1;
+    }
+
+    /// Copies fragment data into place, stashing the packet header carried by fragment 0 right
+    /// in front of the payload so the completed packet can be processed as a regular one.
+    fn store(&mut self, id: usize, fragment_size: usize, header: Option<&[u8]>, data: &[u8]) {
+        if let Some(header) = header {
+            self.header_size = header.len();
+            self.buffer[RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES - header.len()
+                ..RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES]
+                .copy_from_slice(header);
+        }
+
+        if id == self.num_fragments_total - 1 {
+            self.packet_bytes = (self.num_fragments_total - 1) * fragment_size + data.len();
+        }
+
+        let start = RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES + id * fragment_size;
+        self.buffer[start..start + data.len()].copy_from_slice(data);
+    }
+
+    fn packet_range(&self) -> core::ops::Range<usize> {
+        RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES - self.header_size
+            ..RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES + self.packet_bytes
+    }
+}
+
+/// A packet delivered by `Endpoint::recv_with_header`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReceivedPacket {
+    /// Extended sequence of the packet, see `Endpoint::next_sequence`.
+    pub sequence: u32,
+    pub app_header: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct SentData {
+    time: Duration,
+    acked: bool,
+    size: usize,
+}
+
+impl SentData {
+    pub fn new(time: Duration, size: usize) -> Self {
+        Self {
+            time,
+            size,
+            acked: false,
+        }
+    }
+}
+
+impl Default for SentData {
+    fn default() -> Self {
+        Self {
+            time: Duration::ZERO,
+            size: 0,
+            acked: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RecvData {
+    time: Duration,
+    size: usize,
+}
+
+impl RecvData {
+    pub fn new(time: Duration, size: usize) -> Self {
+        Self { time, size }
+    }
+}
+
+impl Default for RecvData {
+    fn default() -> Self {
+        Self {
+            time: Duration::ZERO,
+            size: 0,
+        }
+    }
+}
+
+/// Where an `Endpoint` gets its time from.
+enum TimeSource {
+    /// Made by `new`: the time only moves on `update`.
+    Update,
+    /// Made by `with_clock`: the clock is read on every send, receive and tick. `warned` is set
+    /// once `update` has logged that it is ignored.
+    Clock {
+        clock: Box<dyn Clock + Send>,
+        warned: bool,
+    },
+}
+
+pub struct Endpoint {
+    time_source: TimeSource,
+    time: Duration,
+    rtt: f32,
+    packet_loss: f32,
+    sent_bandwidth_kbps: f32,
+    received_bandwidth_kbps: f32,
+    acked_bandwidth_kbps: f32,
+    counters: Counters,
+    config: EndpointConfig,
+    acks: Vec<u32>,
+    sequence: u32,
+    latest_received: Option<u32>,
+    sent_buffer: SequenceBuffer<SentData>,
+    recv_buffer: SequenceBuffer<RecvData>,
+    reassembly_buffer: SequenceBuffer<ReassemblyData>,
+    buffer_pool: BufferPool,
+    tap: Option<Box<dyn PacketTap + Send>>,
+}
+
+impl Endpoint {
+    /// Creates an endpoint whose time starts at `time`, in seconds, and only moves on `update`.
+    /// Configs that fail `EndpointConfig::validate` are refused.
+    pub fn new(config: EndpointConfig, time: f64) -> Result<Self, ConfigError> {
+        Self::with_time_source(config, TimeSource::Update, clock::from_secs(time))
+    }
+
+    /// Creates an endpoint that reads the time from `clock` whenever it sends or receives, and
+    /// on `tick`.
+    pub fn with_clock<C>(config: EndpointConfig, clock: C) -> Result<Self, ConfigError>
+    where
+        C: Clock + Send + 'static,
+    {
+        let time = clock.now();
+        let time_source = TimeSource::Clock {
+            clock: Box::new(clock),
+            warned: false,
+        };
+        Self::with_time_source(config, time_source, time)
+    }
+
+    fn with_time_source(
+        config: EndpointConfig,
+        time_source: TimeSource,
+        time: Duration,
+    ) -> Result<Self, ConfigError> {
+        config.validate()?;
+
+        trace!("Creating new endpoint named '{}'", config.name);
+        Ok(Self {
+            time_source,
+            time,
+            rtt: 0.0,
+            packet_loss: 0.0,
+            sent_bandwidth_kbps: 0.0,
+            received_bandwidth_kbps: 0.0,
+            acked_bandwidth_kbps: 0.0,
+            counters: Counters::default(),
+            acks: Vec::with_capacity(config.ack_buffer_size),
+            sequence: 0,
+            latest_received: None,
+            sent_buffer: SequenceBuffer::with_capacity(config.sent_packets_buffer_size),
+            recv_buffer: SequenceBuffer::with_capacity(config.received_packets_buffer_size),
+            reassembly_buffer: SequenceBuffer::with_capacity(
+                config.fragment_reassembly_buffer_size,
+            ),
+            buffer_pool: BufferPool::with_capacity(
+                config.buffer_pool_size,
+                RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES
+                    + RELIABLE_WIDE_FRAGMENT_HEADER_BYTES
+                    + config.fragment_size.max(config.fragment_above),
+            ),
+            tap: None,
+            config,
+        })
+    }
+
+    pub fn send(&mut self, packet: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
+        self.send_packet(None, packet)
+    }
+
+    /// Sends `packet` with `app_header` written after the packet header, framed according to
+    /// the `app_header` size registered in the `EndpointConfig`.
+    pub fn send_with_header(
+        &mut self,
+        app_header: &[u8],
+        packet: &[u8],
+    ) -> Result<Vec<Vec<u8>>, ReliableError> {
+        let mut body = self.buffer_pool.acquire();
+        let framing = match self.config.app_header {
+            Some(AppHeaderSize::Fixed(size)) if size == app_header.len() => {
+                Some(AppHeaderFraming::Fixed)
+            }
+            Some(AppHeaderSize::Variable) => u8::try_from(app_header.len()).ok().map(|len| {
+                body.push(len);
+                AppHeaderFraming::LengthPrefixed
+            }),
+            _ => None,
+        };
+        let Some(framing) = framing else {
+            error!(
+                "App header of {} bytes does not match registered size {:?}",
+                app_header.len(),
+                self.config.app_header
+            );
+            self.buffer_pool.release(body);
+            return Err(ReliableError::InvalidAppHeader);
+        };
+        body.extend_from_slice(app_header);
+        body.extend_from_slice(packet);
+
+        let out = self.send_packet(Some(framing), body.as_slice());
+        self.buffer_pool.release(body);
+        out
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn send_packet(
+        &mut self,
+        app_header: Option<AppHeaderFraming>,
+        packet: &[u8],
+    ) -> Result<Vec<Vec<u8>>, ReliableError> {
+        trace_span!(
+            "send",
+            endpoint = %self.config.name,
+            sequence = self.sequence,
+            size = packet.len()
+        );
+
+        self.read_clock();
+        let mut out: Vec<Vec<u8>> = vec![];
+        if packet.is_empty() {
+            error!("Packet too small: Attempting to send an empty packet");
+            return Err(ReliableError::PacketTooSmall);
+        }
+        if packet.len() > self.config.max_packet_size {
+            error!(
+                "Packet too large: Attempting to send {}, max={}",
+                packet.len(),
+                self.config.max_packet_size
+            );
+            self.counters.packets_too_large_to_send += 1;
+            return Err(ReliableError::ExceededMaxPacketSize);
+        }
+
+        let num_fragments = packet.len().div_ceil(self.config.fragment_size);
+        if packet.len() > self.config.fragment_above
+            && num_fragments > self.config.max_fragments as usize
+        {
+            error!(
+                "Packet too large: Attempting to send {} fragments, max={}",
+                num_fragments,
+                self.config.max_fragments
+            );
+            self.counters.packets_too_large_to_send += 1;
+            return Err(ReliableError::ExceededMaxFragments);
+        }
+
+        // Increment sequence
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let mut header = match self.config.ack_format {
+            AckFormat::Narrow => {
+                let (ack, ack_bits) = self.recv_buffer.ack_bits();
+                PacketHeader::new(sequence as u16, ack, ack_bits)
+            }
+            AckFormat::Wide => {
+                let (ack, ack_bits) = self.recv_buffer.wide_ack_bits();
+                PacketHeader::new_wide(sequence as u16, ack, ack_bits)
+            }
+        };
+
+        let send_size = packet.len() + self.config.packet_header_size;
+        let sent = SentData::new(self.time, send_size);
+        self.sent_buffer.insert(sent, sequence as u16)?;
+
+        if let Some(framing) = app_header {
+            header = header.with_app_header(framing);
+        }
+
+        if packet.len() <= self.config.fragment_above {
+            // no fragments
+            // TODO: reimplement this as a cursor
+            trace!("Sending packet {} without fragmentation", sequence);
+
+            out.push(self.write_datagram(&header, packet)?);
+        } else {
+            trace!(
+                "Sending packet {} with fragmentation, size={}, fragments={}",
+                sequence,
+                packet.len(),
+                num_fragments
+            );
+
+            for fragment_id in 0..num_fragments {
+                let fragment = match self.config.fragment_format {
+                    FragmentFormat::Narrow => FragmentHeader::new(
+                        fragment_id as u8,
+                        num_fragments as u8,
+                        header.clone(),
+                    ),
+                    FragmentFormat::Wide => FragmentHeader::new_wide(
+                        fragment_id as u16,
+                        num_fragments as u16,
+                        header.clone(),
+                    ),
+                };
+
+                let cur_start = fragment_id * self.config.fragment_size;
+                let mut cur_end = (fragment_id + 1) * self.config.fragment_size;
+                if cur_end > packet.len() {
+                    cur_end = packet.len();
+                }
+
+                match self.write_datagram(&fragment, &packet[cur_start..cur_end]) {
+                    Ok(buffer) => out.push(buffer),
+                    Err(e) => {
+                        for buffer in out {
+                            self.buffer_pool.release(buffer);
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+            self.counters.fragments_sent += num_fragments as u64;
+        }
+
+        self.counters.packets_sent += 1;
+        trace_event!(debug, datagrams = out.len(), "packet sent");
+        if let Some(tap) = &mut self.tap {
+            for datagram in &out {
+                tap.on_datagram(self.time.as_secs_f64(), Direction::Outgoing, datagram);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Writes `header` followed by `payload` into a buffer from the pool.
+    fn write_datagram<H>(&mut self, header: &H, payload: &[u8]) -> Result<Vec<u8>, ReliableError>
+    where
+        H: Header,
+    {
+        let mut buffer = self.buffer_pool.acquire();
+        buffer.resize(header.size(), 0);
+        if let Err(e) = header.write(&mut Cursor::new(buffer.as_mut_slice())) {
+            self.buffer_pool.release(buffer);
+            return Err(e);
+        }
+        buffer.extend_from_slice(payload);
+        Ok(buffer)
+    }
+
+    pub fn recv(&mut self, packet: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
+        let Some(received) = self.recv_packet(packet)? else {
+            return Ok(Vec::new());
+        };
+        if let Some(app_header) = received.app_header {
+            self.buffer_pool.release(app_header);
+        }
+        Ok(vec![received.payload])
+    }
+
+    /// Like `recv`, but also hands back the application header of each delivered packet.
+    pub fn recv_with_header(&mut self, packet: &[u8]) -> Result<Vec<ReceivedPacket>, ReliableError> {
+        Ok(self.recv_packet(packet)?.into_iter().collect())
+    }
+
+    /// Handles one datagram, which completes at most one packet.
+    fn recv_packet(&mut self, packet: &[u8]) -> Result<Option<ReceivedPacket>, ReliableError> {
+        trace_span!("recv", endpoint = %self.config.name, size = packet.len());
+
+        self.read_clock();
+        if let Some(tap) = &mut self.tap {
+            tap.on_datagram(self.time.as_secs_f64(), Direction::Incoming, packet);
+        }
+        if packet.len() > self.config.max_packet_size {
+            error!(
+                "Packet too large: Attempting to recv {}, max={}",
+                packet.len(),
+                self.config.max_packet_size
+            );
+            self.counters.packets_too_large_to_receive += 1;
+            return Err(ReliableError::ExceededMaxPacketSize);
+        }
+        if packet.is_empty() {
+            return Err(ReliableError::PacketTooSmall);
+        }
+
+        let prefix_byte = packet[0];
+
+        if prefix_byte & 1 == 0 {
+            self.process_packet(packet).map(Some)
+        } else {
+            self.process_fragment(packet)
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn process_packet(&mut self, packet: &[u8]) -> Result<ReceivedPacket, ReliableError> {
+        self.counters.packets_received += 1;
+
+        let mut packet_reader = Cursor::new(packet);
+        let header = match PacketHeader::parse(&mut packet_reader) {
+            Ok(header) => header,
+            Err(e) => {
+                self.counters.packets_invalid += 1;
+                return Err(e);
+            }
+        };
+
+        if !self.recv_buffer.check_sequence(header.sequence()) {
+            error!("Ignoring stale packet: {}", header.sequence());
+            self.counters.packets_stale += 1;
+            return Err(ReliableError::StalePacket);
+        }
+        trace_event!(
+            debug,
+            sequence = header.sequence(),
+            ack = header.ack(),
+            size = packet.len(),
+            "packet received"
+        );
+        let sequence = self.extend_received_sequence(header.sequence());
+
+        let app_header = match header.app_header() {
+            Some(framing) => Some(self.read_app_header(framing, &mut packet_reader)?),
+            None => None,
+        };
+        let inserted = self.recv_buffer.insert(
+            RecvData::new(self.time, self.config.packet_header_size + packet.len()),
+            header.sequence(),
+        );
+        if let Err(e) = inserted {
+            if let Some(app_header) = app_header {
+                self.buffer_pool.release(app_header);
+            }
+            return Err(e);
+        }
+        let mut payload = self.buffer_pool.acquire();
+        payload.extend_from_slice(&packet[packet_reader.position() as usize..packet.len()]);
+        if self.latest_received.is_none_or(|latest| sequence > latest) {
+            self.latest_received = Some(sequence);
+        }
+
+        let mut ack_bits = header.wide_ack_bits();
+        for i in 0..header.ack_format().bits() {
+            if ack_bits & 1 != 0 {
+                let ack_sequence: u16 = (Wrapping(header.ack()) - Wrapping(i)).0;
+
+                let extended_ack = self.extend_sent_sequence(ack_sequence);
+                if let Some(sent_data) = self.sent_buffer.get_mut(ack_sequence) {
+                    if !sent_data.acked && self.acks.len() < self.config.ack_buffer_size {
+                        trace!("mark acked packet: {}", extended_ack);
+                        self.acks.push(extended_ack);
+                        self.counters.packets_acked += 1;
+
+                        sent_data.acked = true;
+                        let rtt = self.time.saturating_sub(sent_data.time).as_secs_f32() * 1000.0;
+                        if (self.rtt == 0.0 && rtt > 0.0) || (self.rtt - rtt).abs() < 0.00001 {
+                            self.rtt = rtt;
+                        } else {
+                            self.rtt += (rtt - self.rtt) * self.config.rtt_smoothing_factor;
+                        }
+                        trace_event!(
+                            debug,
+                            sequence = extended_ack,
+                            rtt_sample = rtt,
+                            "packet acked"
+                        );
+                        trace_event!(trace, rtt = self.rtt, "rtt updated");
+                    }
+                }
+            }
+            ack_bits >>= 1;
+        }
+
+        Ok(ReceivedPacket {
+            sequence,
+            app_header,
+            payload,
+        })
+    }
+
+    /// Extends a sequence received from the peer using the newest one received so far. The
+    /// first packet received is taken to be in the first epoch.
+    fn extend_received_sequence(&self, sequence: u16) -> u32 {
+        match self.latest_received {
+            None => u32::from(sequence),
+            Some(latest) => sequence_buffer::extend_sequence(latest, sequence),
+        }
+    }
+
+    /// Extends the sequence of a packet this endpoint sent, as echoed back in an ack.
+    fn extend_sent_sequence(&self, sequence: u16) -> u32 {
+        sequence_buffer::extend_sequence(self.sequence.wrapping_sub(1), sequence)
+    }
+
+    fn read_app_header(
+        &mut self,
+        framing: AppHeaderFraming,
+        reader: &mut Cursor<&[u8]>,
+    ) -> Result<Vec<u8>, ReliableError> {
+        let size = match (framing, self.config.app_header) {
+            (AppHeaderFraming::LengthPrefixed, _) => usize::from(reader.read_u8()?),
+            (AppHeaderFraming::Fixed, Some(AppHeaderSize::Fixed(size))) => size,
+            (AppHeaderFraming::Fixed, _) => {
+                error!("Received fixed size app header, but none is registered");
+                return Err(ReliableError::InvalidAppHeader);
+            }
+        };
+
+        let mut app_header = self.buffer_pool.acquire();
+        app_header.resize(size, 0);
+        if reader.read_exact(app_header.as_mut_slice()).is_err() {
+            self.buffer_pool.release(app_header);
+            return Err(ReliableError::InvalidAppHeader);
+        }
+        Ok(app_header)
+    }
+
+    fn process_fragment(&mut self, packet: &[u8]) -> Result<Option<ReceivedPacket>, ReliableError> {
+        let completed = match self.reassemble_fragment(packet) {
+            Ok(completed) => completed,
+            Err(e) => {
+                self.counters.fragments_invalid += 1;
+                trace_event!(debug, error = ?e, size = packet.len(), "fragment refused");
+                return Err(e);
+            }
+        };
+        self.counters.fragments_received += 1;
+
+        let Some(completed) = completed else {
+            return Ok(None);
+        };
+        let received = self.process_packet(&completed.buffer[completed.packet_range()]);
+        completed.release(&mut self.buffer_pool);
+        received.map(Some)
+    }
+
+    /// Stores a fragment, handing back the reassembly once all fragments of the packet have
+    /// arrived.
+    #[allow(clippy::cast_possible_truncation)]
+    fn reassemble_fragment(
+        &mut self,
+        packet: &[u8],
+    ) -> Result<Option<ReassemblyData>, ReliableError> {
+        let mut packet_reader = Cursor::new(packet);
+        let header = FragmentHeader::parse(&mut packet_reader)?;
+
+        trace!(
+            "parsed fragment header correctly, processing reassembly..: id={}, s={}",
+            header.sequence(),
+            header.id()
+        );
+
+        let id = usize::from(header.id());
+        let count = usize::from(header.count());
+        if count > self.config.max_fragments as usize {
+            error!(
+                "num fragments {} outside of range of max fragments {}",
+                count, self.config.max_fragments
+            );
+            return Err(ReliableError::InvalidFragment);
+        }
+
+        // The embedded packet header ends wherever parsing it stopped, which is past `size()` for
+        // headers that spell out bytes the canonical form leaves implicit.
+        let data_start = header.format().header_size();
+        let header_end = packet_reader.position() as usize;
+        let (packet_header, data) = match header.packet_header() {
+            Some(_) => (Some(&packet[data_start..header_end]), &packet[header_end..]),
+            None => (None, &packet[header_end..]),
+        };
+        if data.len() > self.config.fragment_size
+            || (id != count - 1 && data.len() != self.config.fragment_size)
+        {
+            error!(
+                "fragment {} is {} bytes, which is not the expected fragment size {}",
+                id,
+                data.len(),
+                self.config.fragment_size
+            );
+            return Err(ReliableError::InvalidFragment);
+        }
+
+        if self.reassembly_buffer.get(header.sequence()).is_none() {
+            // Reassemblies pushed out of the window are never completed, but their buffers
+            // still go back to the pool.
+            let pool = &mut self.buffer_pool;
+            let slot = self.reassembly_buffer.insert_with(
+                ReassemblyData::default(),
+                header.sequence(),
+                |evicted| evicted.release(pool),
+            )?;
+            *slot = ReassemblyData::new(
+                header.sequence(),
+                count,
+                self.config.fragment_size,
+                self.buffer_pool.acquire(),
+                self.buffer_pool.acquire(),
+            );
+        }
+        let Some(reassembly_data) = self.reassembly_buffer.get_mut(header.sequence()) else {
+            return Err(ReliableError::InvalidFragment);
+        };
+
+        // Got the data
+        if reassembly_data.num_fragments_total != count {
+            return Err(ReliableError::InvalidFragment);
+        }
+
+        if reassembly_data.is_received(id) {
+            return Err(ReliableError::InvalidFragment);
+        }
+
+        reassembly_data.mark_received(id);
+        if let Some(packet_header) = header.packet_header() {
+            reassembly_data.ack = packet_header.ack();
+            reassembly_data.ack_bits = packet_header.wide_ack_bits();
+        }
+
+        trace!(
+            "{}: received fragment #{}/{} of packet {}, received={}",
+            self.config.name,
+            id + 1,
+            count,
+            header.sequence(),
+            reassembly_data.num_fragments_received
+        );
+
+        reassembly_data.store(id, self.config.fragment_size, packet_header, data);
+        trace_event!(
+            debug,
+            sequence = header.sequence(),
+            id,
+            count,
+            size = data.len(),
+            received = reassembly_data.num_fragments_received,
+            "fragment received"
+        );
+
+        if reassembly_data.num_fragments_received == reassembly_data.num_fragments_total {
+            let completed = core::mem::take(reassembly_data);
+            self.reassembly_buffer.remove(completed.sequence);
+            trace_event!(
+                debug,
+                sequence = completed.sequence,
+                size = completed.packet_bytes,
+                fragments = count,
+                "fragments reassembled"
+            );
+            return Ok(Some(completed));
+        }
+
+        Ok(None)
+    }
+
+    /// Shows `tap` every datagram sent or received from now on, replacing any previous tap.
+    pub fn set_tap(&mut self, tap: Box<dyn PacketTap + Send>) {
+        self.tap = Some(tap);
+    }
+
+    /// Removes the tap installed with `set_tap`.
+    pub fn take_tap(&mut self) -> Option<Box<dyn PacketTap + Send>> {
+        self.tap.take()
+    }
+
+    /// Hands a buffer returned by `send` or `recv` back to the endpoint's pool for reuse.
+    pub fn release_buffer(&mut self, buffer: Vec<u8>) {
+        self.buffer_pool.release(buffer);
+    }
+
+    /// Number of times the endpoint had to allocate because its buffer pool was empty.
+    pub fn buffer_pool_misses(&self) -> u64 {
+        self.buffer_pool.misses()
+    }
+
+    /// Moves the time of an endpoint made by `new` to `time`, in seconds, unless that is behind
+    /// it, then `tick`s. Endpoints made by `with_clock` keep to their clock and only tick, with a
+    /// warning the first time.
+    pub fn update(&mut self, time: f64) {
+        match &mut self.time_source {
+            TimeSource::Update => self.time = self.time.max(clock::from_secs(time)),
+            TimeSource::Clock { warned, .. } => {
+                if !*warned {
+                    *warned = true;
+                    warn!(
+                        "{}: update({}) ignored, the endpoint runs on its own clock",
+                        self.config.name, time
+                    );
+                }
+            }
+        }
+        self.tick();
+    }
+
+    /// Reads the time from the clock of an endpoint made by `with_clock`.
+    fn read_clock(&mut self) {
+        if let TimeSource::Clock { clock, .. } = &self.time_source {
+            self.time = clock.now();
+        }
+    }
+
+    /// Reads the clock and refreshes the packet loss and bandwidth estimates from the oldest half
+    /// of the sent and received buffers.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn tick(&mut self) {
+        self.read_clock();
+
+        let sent = oldest_half(&self.sent_buffer);
+        let num_samples = sent.len().max(1);
+        let num_dropped = sent.iter().flatten().filter(|sent| !sent.acked).count();
+        let packet_loss = num_dropped as f32 / num_samples as f32 * 100.0;
+        smooth(
+            &mut self.packet_loss,
+            packet_loss,
+            self.config.packet_loss_smoothing_factor,
+        );
+
+        let factor = self.config.bandwidth_smoothing_factor;
+        let sent_samples = sent.iter().flatten().map(|sent| (sent.time, sent.size));
+        if let Some(kbps) = bandwidth_kbps(sent_samples) {
+            smooth(&mut self.sent_bandwidth_kbps, kbps, factor);
+        }
+        let acked_samples = sent
+            .iter()
+            .flatten()
+            .filter(|sent| sent.acked)
+            .map(|sent| (sent.time, sent.size));
+        if let Some(kbps) = bandwidth_kbps(acked_samples) {
+            smooth(&mut self.acked_bandwidth_kbps, kbps, factor);
+        }
+        let received = oldest_half(&self.recv_buffer);
+        let received_samples = received.iter().flatten().map(|recv| (recv.time, recv.size));
+        if let Some(kbps) = bandwidth_kbps(received_samples) {
+            smooth(&mut self.received_bandwidth_kbps, kbps, factor);
+        }
+    }
+
+    /// Smoothed round trip time in milliseconds.
+    pub fn rtt(&self) -> f32 {
+        self.rtt
+    }
+
+    /// Smoothed percentage of sent packets that were not acked, see `update`.
+    pub fn packet_loss(&self) -> f32 {
+        self.packet_loss
+    }
+
+    /// Smoothed sent, received and acked bandwidth in kbps, see `update`.
+    pub fn bandwidth(&self) -> (f32, f32, f32) {
+        (
+            self.sent_bandwidth_kbps,
+            self.received_bandwidth_kbps,
+            self.acked_bandwidth_kbps,
+        )
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    /// Takes the endpoint's stats, labelled with `EndpointConfig::name`.
+    pub fn metrics(&self) -> EndpointMetrics {
+        EndpointMetrics::new(&self.config.name, self)
+    }
+
+    pub fn reset(&mut self) {
+        self.sequence = 0;
+        self.latest_received = None;
+
+        self.acks.clear();
+        self.sent_buffer.reset();
+        self.recv_buffer.reset();
+        let pool = &mut self.buffer_pool;
+        self.reassembly_buffer
+            .reset_with(|reassembly| reassembly.release(pool));
+    }
+
+    /// Extended sequence the next packet will be sent with. The low 16 bits are the sequence
+    /// on the wire and the high 16 bits count how often it has wrapped.
+    pub fn next_sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Extended sequences of the sent packets acked so far.
+    pub fn acks(&self) -> &[u32] {
+        self.acks.as_slice()
+    }
+
+    pub fn clear_acks(&mut self) {
+        self.acks.clear();
+    }
+}
+
+impl ReliableEndpoint for Endpoint {
+    fn send(&mut self, packet: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
+        Endpoint::send(self, packet)
+    }
+
+    fn receive(&mut self, datagram: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
+        Endpoint::recv(self, datagram)
+    }
+
+    fn update(&mut self, time: f64) {
+        Endpoint::update(self, time);
+    }
+
+    fn acks(&self) -> Vec<u32> {
+        Endpoint::acks(self).to_vec()
+    }
+
+    fn clear_acks(&mut self) {
+        Endpoint::clear_acks(self);
+    }
+
+    fn reset(&mut self) {
+        Endpoint::reset(self);
+    }
+
+    fn rtt(&self) -> f32 {
+        Endpoint::rtt(self)
+    }
+
+    fn packet_loss(&self) -> f32 {
+        Endpoint::packet_loss(self)
+    }
+
+    fn bandwidth(&self) -> (f32, f32, f32) {
+        Endpoint::bandwidth(self)
+    }
+
+    fn counters(&self) -> Counters {
+        Endpoint::counters(self)
+    }
+}
+
+/// Entries for the oldest half of the window of `buffer`, which have had the longest to be acked.
+#[allow(clippy::cast_possible_truncation)]
+fn oldest_half<T>(buffer: &SequenceBuffer<T>) -> Vec<Option<&T>>
+where
+    T: Default + Clone + Send + Sync,
+{
+    let base = buffer.sequence().wrapping_sub(buffer.len() as u16);
+    (0..buffer.len() / 2)
+        .map(|i| buffer.get(base.wrapping_add(i as u16)))
+        .collect()
+}
+
+/// Bytes over the span of time the `(time, bytes)` samples were taken in, if there is a span.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn bandwidth_kbps(samples: impl Iterator<Item = (Duration, usize)>) -> Option<f32> {
+    let mut bytes = 0;
+    let mut start_time = Duration::MAX;
+    let mut finish_time = Duration::ZERO;
+    for (time, size) in samples {
+        bytes += size;
+        start_time = start_time.min(time);
+        finish_time = finish_time.max(time);
+    }
+    if finish_time > start_time {
+        let seconds = finish_time.saturating_sub(start_time).as_secs_f64();
+        Some((bytes as f64 / seconds * 8.0 / 1000.0) as f32)
+    } else {
+        None
+    }
+}
+
+/// Moves `value` toward `sample` by `factor`, or straight to it when they are close.
+fn smooth(value: &mut f32, sample: f32, factor: f32) {
+    if (*value - sample).abs() > 0.00001 {
+        *value += (sample - *value) * factor;
+    } else {
+        *value = sample;
+    }
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::items_after_statements
+)]
+mod tests {
+    use super::*;
+    use crate::tests::enable_logging;
+    use crate::{render_prometheus, CapturedDatagram};
+    #[cfg(feature = "std")]
+    use crate::{InstantClock, MetricsServer, PcapReader, PcapWriter};
+
+    fn test_compare<T>(one: &[T], two: &[T]) -> bool
+        where
+            T: PartialEq,
+    {
+        if one.len() != two.len() {
+            return false;
+        }
+        for i in 0..one.len() {
+            if one[i] != two[i] {
+                return false;
+            }
+        }
+        true
+    }
+
+    const TEST_FRAGMENTS_NUM_ITERATIONS: usize = 200;
+
+    #[test]
+    fn fragments() {
+        enable_logging();
+
+        let mut time = 100.0;
+        let test_data_remainder = [0x41; 4092];
+        let test_data_align = [0x41; 2048];
+
+        let mut one = Endpoint::new(EndpointConfig::new("one"), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), time).unwrap();
+
+        let delta_time = 0.01;
+        for test_data in &[&test_data_align[..], &test_data_remainder[..]] {
+            for _ in 0..TEST_FRAGMENTS_NUM_ITERATIONS {
+                // forward packets to their endpoints
+                for packet in one.send(test_data).unwrap() {
+                    for data in two.recv(packet.as_slice()).unwrap() {
+                        assert!(test_compare(data.as_slice(), test_data));
+                    }
+                }
+                for packet in two.send(test_data).unwrap() {
+                    for data in one.recv(packet.as_slice()).unwrap() {
+                        assert!(test_compare(data.as_slice(), test_data));
+                    }
+                }
+
+                time += delta_time;
+                one.update(time);
+                two.update(time);
+            }
+        }
+    }
+
+    #[test]
+    fn fragment_with_non_canonical_packet_header() {
+        enable_logging();
+
+        let config = EndpointConfig::new("one");
+        let fragment_size = config.fragment_size;
+        let mut one = Endpoint::new(config, 100.0).unwrap();
+        let payload: Vec<u8> = (0..fragment_size + 100).map(|i| i as u8).collect();
+
+        // The first fragment's packet header writes its ack as a u16 and its first ack bits byte
+        // out as 0xFF, two bytes more than the canonical header would take.
+        let mut first = vec![1, 0, 0, 0, 2, 1 << 1, 0, 0, 0xFF, 0xFF, 0xFF];
+        first.extend_from_slice(&payload[..fragment_size]);
+        let mut second = vec![1, 0, 0, 1, 2];
+        second.extend_from_slice(&payload[fragment_size..]);
+
+        assert!(one.recv(&first).unwrap().is_empty());
+        assert_eq!(one.recv(&second).unwrap(), vec![payload]);
+    }
+
+    const TEST_ACKS_NUM_ITERATIONS: usize = 200;
+
+    #[test]
+    fn acks() {
+        enable_logging();
+
+        let mut time = 100.0;
+        let test_data = [0x41; 24];
+
+        let mut one = Endpoint::new(EndpointConfig::new("one"), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), time).unwrap();
+
+        let delta_time = 0.01;
+        for _ in 0..TEST_ACKS_NUM_ITERATIONS {
+            // forward packets to their endpoints
+            for packet in one.send(&test_data).unwrap() {
+                trace!("ONE: Sending packet: len={}", packet.len());
+                for data in two.recv(packet.as_slice()).unwrap() {
+                    assert_eq!(data.as_slice(), &test_data[..]);
+                }
+            }
+            for packet in two.send(&test_data).unwrap() {
+                trace!("TWO: Sending packet: len={}", packet.len());
+                for data in one.recv(packet.as_slice()).unwrap() {
+                    assert_eq!(data.as_slice(), &test_data[..]);
+                }
+            }
+
+            time += delta_time;
+            one.update(time);
+            two.update(time);
+        }
+
+        let mut one_acked = [false; TEST_ACKS_NUM_ITERATIONS];
+        for ack in one.acks() {
+            if (*ack as usize) < TEST_ACKS_NUM_ITERATIONS {
+                one_acked[*ack as usize] = true;
+            }
+        }
+        // Every packet but the last one is acked by the next reply.
+        for acked in one_acked.iter().take(TEST_ACKS_NUM_ITERATIONS - 1) {
+            assert!(acked);
+        }
+    }
+
+    #[test]
+    fn extended_sequences() {
+        enable_logging();
+
+        let mut one = Endpoint::new(EndpointConfig::new("one"), 100.0).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), 100.0).unwrap();
+
+        let mut late = vec![];
+        for i in 0..65536 + 100 {
+            assert_eq!(one.next_sequence(), i);
+            let mut received = vec![];
+            for packet in one.send(&[0x41; 8]).unwrap() {
+                received.extend(two.recv_with_header(&packet).unwrap());
+            }
+            assert_eq!(received.len(), 1);
+            assert_eq!(received[0].sequence, i);
+
+            let reply = two.send(&[0x42; 8]).unwrap();
+            if i == 65500 {
+                late = reply;
+                continue;
+            }
+            for packet in reply {
+                one.recv(&packet).unwrap();
+            }
+            if i == 65501 {
+                assert_eq!(one.acks(), &[65501, 65500]);
+            } else {
+                assert_eq!(one.acks(), &[i]);
+            }
+            one.clear_acks();
+        }
+
+        // A reply held back from before the wrap is still placed in the first epoch.
+        let received = one.recv_with_header(&late[0]).unwrap();
+        assert_eq!(received[0].sequence, 65500);
+
+        one.reset();
+        assert_eq!(one.next_sequence(), 0);
+    }
+
+    #[test]
+    fn wide_fragments() {
+        enable_logging();
+
+        let mut config = EndpointConfig::new("wide");
+        config.fragment_size = 64;
+        config.fragment_above = 64;
+        config.max_fragments = 1000;
+        config.max_packet_size = 64 * 1000;
+        config.fragment_format = FragmentFormat::Wide;
+
+        let mut one = Endpoint::new(config.clone(), 0.0).unwrap();
+        let mut two = Endpoint::new(config.clone(), 0.0).unwrap();
+
+        let test_data: Vec<u8> = (0..40_000).map(|i| i as u8).collect();
+        let mut fragments = one.send(&test_data).unwrap();
+        assert_eq!(fragments.len(), 625);
+
+        // Deliver out of order; only the final fragment completes the packet.
+        fragments.reverse();
+        let mut received = vec![];
+        for fragment in &fragments {
+            received.extend(two.recv(fragment).unwrap());
+        }
+        assert_eq!(received.len(), 1);
+        assert!(test_compare(received[0].as_slice(), test_data.as_slice()));
+
+        config.fragment_format = FragmentFormat::Narrow;
+        assert!(Endpoint::new(config.clone(), 0.0).is_err());
+
+        // The narrow format tops out at 255 fragments, not 256.
+        config.max_fragments = 255;
+        config.max_packet_size = 64 * 255;
+        let mut one = Endpoint::new(config.clone(), 0.0).unwrap();
+        let mut two = Endpoint::new(config.clone(), 0.0).unwrap();
+        let mut received = vec![];
+        for fragment in one.send(&test_data[..64 * 255]).unwrap() {
+            received.extend(two.recv(&fragment).unwrap());
+        }
+        assert_eq!(received.len(), 1);
+        assert!(test_compare(received[0].as_slice(), &test_data[..64 * 255]));
+
+        config.max_fragments = 256;
+        assert!(Endpoint::new(config, 0.0).is_err());
+    }
+
+    #[test]
+    fn app_headers() {
+        enable_logging();
+
+        let mut config = EndpointConfig::new("fixed");
+        config.app_header = Some(AppHeaderSize::Fixed(3));
+        let mut one = Endpoint::new(config.clone(), 0.0).unwrap();
+        let mut two = Endpoint::new(config, 0.0).unwrap();
+
+        let small = [0x41; 24];
+        let large = [0x42; 4092];
+        for test_data in &[&small[..], &large[..]] {
+            let mut received = vec![];
+            for packet in one.send_with_header(&[1, 2, 3], test_data).unwrap() {
+                received.extend(two.recv_with_header(&packet).unwrap());
+            }
+            assert_eq!(received.len(), 1);
+            assert_eq!(received[0].app_header, Some(vec![1, 2, 3]));
+            assert!(test_compare(received[0].payload.as_slice(), test_data));
+        }
+        assert!(one.send_with_header(&[1, 2], &small).is_err());
+
+        let packets = one.send(&small).unwrap();
+        let received = two.recv_with_header(&packets[0]).unwrap();
+        assert_eq!(received[0].app_header, None);
+
+        let mut config = EndpointConfig::new("variable");
+        config.app_header = Some(AppHeaderSize::Variable);
+        let mut one = Endpoint::new(config.clone(), 0.0).unwrap();
+        let mut two = Endpoint::new(config, 0.0).unwrap();
+
+        for app_header in &[&[][..], &[7; 5][..], &[9; 255][..]] {
+            let packets = one.send_with_header(app_header, &small).unwrap();
+            let received = two.recv_with_header(&packets[0]).unwrap();
+            assert_eq!(received[0].app_header.as_deref(), Some(*app_header));
+            assert_eq!(received[0].payload.as_slice(), &small[..]);
+        }
+        assert!(one.send_with_header(&[0; 256], &small).is_err());
+
+        let mut unregistered = Endpoint::new(EndpointConfig::new("none"), 0.0).unwrap();
+        assert!(unregistered.send_with_header(&[1], &small).is_err());
+    }
+
+    fn acks_after_lost_replies(ack_format: AckFormat) -> usize {
+        const SENT: usize = 48;
+        const LOST: usize = 40;
+
+        let config = |name| {
+            EndpointConfig::builder(name)
+                .ack_format(ack_format)
+                .build()
+                .unwrap()
+        };
+        let mut one = Endpoint::new(config("one"), 100.0).unwrap();
+        let mut two = Endpoint::new(config("two"), 100.0).unwrap();
+
+        for i in 0..SENT {
+            for packet in one.send(&[i as u8; 16]).unwrap() {
+                two.recv(&packet).unwrap();
+            }
+            for packet in two.send(&[i as u8; 16]).unwrap() {
+                if i >= LOST {
+                    one.recv(&packet).unwrap();
+                }
+            }
+        }
+        one.acks().len()
+    }
+
+    #[test]
+    fn wide_acks() {
+        enable_logging();
+
+        // The first reply to get through acks packet 40, so a 32 packet window misses 0 to 8.
+        assert_eq!(acks_after_lost_replies(AckFormat::Narrow), 39);
+        assert_eq!(acks_after_lost_replies(AckFormat::Wide), 48);
+
+        // Fragment 0 carries the longer header through reassembly.
+        let config = EndpointConfig::builder("wide")
+            .ack_format(AckFormat::Wide)
+            .build()
+            .unwrap();
+        let mut one = Endpoint::new(config.clone(), 100.0).unwrap();
+        let mut two = Endpoint::new(config, 100.0).unwrap();
+        let test_data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        let mut received = vec![];
+        for packet in one.send(&test_data).unwrap() {
+            received.extend(two.recv(&packet).unwrap());
+        }
+        assert_eq!(received, vec![test_data]);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        enable_logging();
+
+        let mut time = 100.0;
+        let config = EndpointConfig::new("one");
+        let mut one = Endpoint::new(config.clone(), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), time).unwrap();
+
+        for i in 0..40 {
+            for packet in one.send(&[i; 24]).unwrap() {
+                two.recv(&packet).unwrap();
+            }
+            for packet in two.send(&[i; 24]).unwrap() {
+                one.recv(&packet).unwrap();
+            }
+            time += 0.01;
+            one.update(time);
+            two.update(time);
+        }
+
+        // Leave a fragmented packet half reassembled.
+        let large: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        let fragments = two.send(&large).unwrap();
+        for fragment in &fragments[..2] {
+            assert!(one.recv(fragment).unwrap().is_empty());
+        }
+
+        let snapshot = one.snapshot();
+        let mut restored = Endpoint::restore(config.clone(), &snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.next_sequence(), one.next_sequence());
+
+        let mut reassembled = vec![];
+        for fragment in &fragments[2..] {
+            let received = restored.recv(fragment).unwrap();
+            assert_eq!(received, one.recv(fragment).unwrap());
+            reassembled.extend(received);
+        }
+        assert_eq!(reassembled, vec![large]);
+        assert_eq!(restored.acks(), one.acks());
+
+        // The restored endpoint carries on exactly as the original would have.
+        for i in 40..80 {
+            let sent = restored.send(&[i; 24]).unwrap();
+            assert_eq!(sent, one.send(&[i; 24]).unwrap());
+            for packet in sent {
+                assert_eq!(two.recv(&packet).unwrap(), vec![vec![i; 24]]);
+            }
+            for packet in two.send(&[i; 24]).unwrap() {
+                assert_eq!(restored.recv(&packet).unwrap(), one.recv(&packet).unwrap());
+            }
+            time += 0.01;
+            one.update(time);
+            restored.update(time);
+            two.update(time);
+        }
+        assert_eq!(restored.acks(), one.acks());
+        assert_eq!(restored.snapshot(), one.snapshot());
+
+        for len in 0..snapshot.len() {
+            assert!(matches!(
+                Endpoint::restore(config.clone(), &snapshot[..len]),
+                Err(ReliableError::InvalidSnapshot)
+            ));
+        }
+        let smaller = EndpointConfig::builder("one")
+            .sent_packets_buffer_size(128)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            Endpoint::restore(smaller, &snapshot),
+            Err(ReliableError::InvalidSnapshot)
+        ));
+    }
+
+    #[test]
+    fn snapshot_restore_stats() {
+        enable_logging();
+
+        // Lose one packet in four so the loss estimate has something to show.
+        fn exchange(one: &mut Endpoint, two: &mut Endpoint, i: u32, time: f64) {
+            for packet in one.send(&i.to_le_bytes()).unwrap() {
+                if !i.is_multiple_of(4) {
+                    two.recv(&packet).unwrap();
+                }
+            }
+            for packet in two.send(&i.to_le_bytes()).unwrap() {
+                one.recv(&packet).unwrap();
+            }
+            one.update(time);
+            two.update(time);
+        }
+
+        let mut time = 100.0;
+        let mut one = Endpoint::new(EndpointConfig::new("one"), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), time).unwrap();
+        for i in 0..300 {
+            time += 0.01;
+            exchange(&mut one, &mut two, i, time);
+        }
+        assert!(one.packet_loss() > 0.0);
+        assert!(one.bandwidth().0 > 0.0);
+
+        let mut restored_one =
+            Endpoint::restore(EndpointConfig::new("one"), &one.snapshot()).unwrap();
+        let mut restored_two =
+            Endpoint::restore(EndpointConfig::new("two"), &two.snapshot()).unwrap();
+        assert_eq!(restored_one.counters(), one.counters());
+        assert_eq!(
+            restored_one.packet_loss().to_bits(),
+            one.packet_loss().to_bits()
+        );
+        assert_eq!(restored_one.bandwidth(), one.bandwidth());
+
+        // The restored pair carries on exactly as the original one.
+        for i in 300..400 {
+            time += 0.01;
+            exchange(&mut one, &mut two, i, time);
+            exchange(&mut restored_one, &mut restored_two, i, time);
+        }
+        assert_eq!(restored_one.counters(), one.counters());
+        assert_eq!(
+            restored_one.packet_loss().to_bits(),
+            one.packet_loss().to_bits()
+        );
+        assert_eq!(restored_one.bandwidth(), one.bandwidth());
+        assert_eq!(restored_two.counters(), two.counters());
+    }
+
+    #[test]
+    #[cfg(target_has_atomic = "64")]
+    fn snapshot_restore_on_clock() {
+        use crate::VirtualClock;
+
+        let clock = VirtualClock::new(Duration::from_secs(100));
+        let config = EndpointConfig::new("one");
+        let mut one = Endpoint::with_clock(config.clone(), clock.clone()).unwrap();
+        let mut two = Endpoint::with_clock(EndpointConfig::new("two"), clock.clone()).unwrap();
+        for packet in one.send(&[0x41; 24]).unwrap() {
+            two.recv(&packet).unwrap();
+        }
+
+        // A snapshot remembers which kind of endpoint it was taken of.
+        let snapshot = one.snapshot();
+        assert!(matches!(
+            Endpoint::restore(config.clone(), &snapshot),
+            Err(ReliableError::InvalidSnapshot)
+        ));
+        let unclocked = Endpoint::new(config.clone(), 100.0).unwrap().snapshot();
+        assert!(matches!(
+            Endpoint::restore_with_clock(config.clone(), clock.clone(), &unclocked),
+            Err(ReliableError::InvalidSnapshot)
+        ));
+
+        // The restored endpoint keeps following the clock.
+        let mut restored = Endpoint::restore_with_clock(config, clock.clone(), &snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        clock.advance(Duration::from_millis(50));
+        for packet in two.send(&[0x42; 24]).unwrap() {
+            restored.recv(&packet).unwrap();
+        }
+        assert_eq!(restored.acks(), &[0]);
+        assert!((restored.rtt() - 50.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn snapshot_restore_after_wrap() {
+        enable_logging();
+
+        // Buffer sizes that don't divide 65536 map slots by how often the buffer has wrapped.
+        let config = EndpointConfig::builder("one")
+            .sent_packets_buffer_size(100)
+            .received_packets_buffer_size(100)
+            .fragment_reassembly_buffer_size(30)
+            .build()
+            .unwrap();
+        let mut one = Endpoint::new(config.clone(), 100.0).unwrap();
+        let mut two = Endpoint::new(config.clone(), 100.0).unwrap();
+
+        for i in 0..65_600_u32 {
+            for packet in one.send(&i.to_le_bytes()).unwrap() {
+                two.recv(&packet).unwrap();
+            }
+            for packet in two.send(&i.to_le_bytes()).unwrap() {
+                one.recv(&packet).unwrap();
+            }
+        }
+        assert!(one.next_sequence() > 65536);
+
+        let large: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        let fragments = two.send(&large).unwrap();
+        assert_eq!(fragments.len(), 5);
+        for fragment in &fragments[..2] {
+            assert!(one.recv(fragment).unwrap().is_empty());
+        }
+
+        let snapshot = one.snapshot();
+        let mut restored = Endpoint::restore(config.clone(), &snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+
+        for fragment in &fragments[2..] {
+            assert_eq!(restored.recv(fragment).unwrap(), one.recv(fragment).unwrap());
+        }
+        for i in 0..300_u32 {
+            let sent = restored.send(&i.to_le_bytes()).unwrap();
+            assert_eq!(sent, one.send(&i.to_le_bytes()).unwrap());
+            for packet in sent {
+                two.recv(&packet).unwrap();
+            }
+            for packet in two.send(&i.to_le_bytes()).unwrap() {
+                assert_eq!(restored.recv(&packet).unwrap(), one.recv(&packet).unwrap());
+            }
+        }
+        assert_eq!(restored.snapshot(), one.snapshot());
+
+        // The reassembly is the last entry. Its received count is followed by four more lengths,
+        // one bitset word and the length-prefixed buffer.
+        let buffer_len = RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES + 5 * config.fragment_size;
+        let received_at = snapshot.len() - buffer_len - 4 - 8 - 4 * 5;
+        assert_eq!(snapshot[received_at..received_at + 4], 2_u32.to_le_bytes());
+        let mut miscounted = snapshot.clone();
+        miscounted[received_at] = 3;
+        assert!(matches!(
+            Endpoint::restore(config, &miscounted),
+            Err(ReliableError::InvalidSnapshot)
+        ));
+    }
+
+    #[test]
+    fn buffer_pool() {
+        enable_logging();
+
+        let mut time = 100.0;
+        let small = [0x41; 24];
+        let large = [0x42; 4092];
+
+        let mut one = Endpoint::new(EndpointConfig::new("one"), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), time).unwrap();
+
+        for _ in 0..100 {
+            for test_data in &[&small[..], &large[..]] {
+                for packet in one.send(test_data).unwrap() {
+                    for data in two.recv(packet.as_slice()).unwrap() {
+                        assert!(test_compare(data.as_slice(), test_data));
+                        two.release_buffer(data);
+                    }
+                    one.release_buffer(packet);
+                }
+            }
+
+            time += 0.01;
+            one.update(time);
+            two.update(time);
+        }
+        assert_eq!(one.buffer_pool_misses(), 0);
+        assert_eq!(two.buffer_pool_misses(), 0);
+
+        // Resetting mid-reassembly hands the reassembly's buffers back.
+        let fragments = one.send(&large).unwrap();
+        assert!(two.recv(&fragments[0]).unwrap().is_empty());
+        let available = two.buffer_pool.available();
+        two.reset();
+        assert_eq!(two.buffer_pool.available(), available + 2);
+
+        let mut pool = BufferPool::with_capacity(1, 16);
+        let buffer = pool.acquire();
+        assert_eq!(pool.misses(), 0);
+        let extra = pool.acquire();
+        assert_eq!(pool.misses(), 1);
+        pool.release(buffer);
+        pool.release(extra);
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[test]
+    fn buffer_pool_steady_state() {
+        enable_logging();
+
+        let mut time = 100.0;
+        let mut config = EndpointConfig::new("lossy");
+        config.app_header = Some(AppHeaderSize::Fixed(2));
+        let mut one = Endpoint::new(config.clone(), time).unwrap();
+        let mut two = Endpoint::new(config, time).unwrap();
+
+        let large = [0x42; 4092];
+        let mut misses = None;
+        for i in 0..500 {
+            // Refused app headers, lost fragments and stale reassemblies pushed out of the
+            // window must all hand their buffers back.
+            assert!(one.send_with_header(&[1, 2, 3], &large).is_err());
+            let mut fragments = one.send_with_header(&[1, 2], &large).unwrap();
+            if i % 2 == 0 {
+                one.release_buffer(fragments.pop().unwrap());
+            }
+            for fragment in fragments {
+                for received in two.recv_with_header(&fragment).unwrap() {
+                    two.release_buffer(received.payload);
+                    two.release_buffer(received.app_header.unwrap());
+                }
+                one.release_buffer(fragment);
+            }
+            for packet in two.send_with_header(&[3, 4], &[0x41; 24]).unwrap() {
+                for data in one.recv(&packet).unwrap() {
+                    one.release_buffer(data);
+                }
+                two.release_buffer(packet);
+            }
+
+            time += 0.01;
+            one.update(time);
+            two.update(time);
+
+            // Once the reassembly window has filled, nothing more is allocated.
+            if i == 100 {
+                misses = Some((one.buffer_pool_misses(), two.buffer_pool_misses()));
+            }
+        }
+        assert_eq!(
+            misses,
+            Some((one.buffer_pool_misses(), two.buffer_pool_misses()))
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    #[cfg(feature = "std")]
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn pcap_capture() {
+        enable_logging();
+
+        let mut time = 100.0;
+        let capture = SharedBuffer::default();
+        let mut one = Endpoint::new(EndpointConfig::new("one"), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), time).unwrap();
+        one.set_tap(Box::new(PcapWriter::new(capture.clone()).unwrap()));
+
+        let mut expected = Vec::new();
+        for i in 0..10 {
+            let size = if i == 5 { 3000 } else { 100 };
+            for packet in one.send(&vec![0x41; size]).unwrap() {
+                expected.push((time, Direction::Outgoing, packet.len()));
+                two.recv(&packet).unwrap();
+            }
+            for packet in two.send(&[0x42; 50]).unwrap() {
+                expected.push((time, Direction::Incoming, packet.len()));
+                one.recv(&packet).unwrap();
+            }
+            time += 0.25;
+            one.update(time);
+            two.update(time);
+        }
+        assert!(one.take_tap().is_some());
+        one.send(&[0x43; 10]).unwrap();
+
+        let bytes = capture.0.lock().unwrap().clone();
+        let u16_be = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+        let u32_le = |at: usize| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+        assert_eq!(u32_le(0), 0xA1B2_C3D4);
+        assert_eq!(u32_le(20), 101);
+
+        let mut at = 24;
+        for &(time, direction, len) in &expected {
+            let stamp = f64::from(u32_le(at)) + f64::from(u32_le(at + 4)) / 1e6;
+            assert!((stamp - time).abs() < 1e-6);
+            assert_eq!(u32_le(at + 8) as usize, 28 + len);
+            assert_eq!(u32_le(at + 12) as usize, 28 + len);
+            at += 16;
+
+            assert_eq!(bytes[at], 0x45);
+            assert_eq!(bytes[at + 9], 17);
+            assert_eq!(u16_be(at + 2) as usize, 28 + len);
+            let sum: u32 = (0..20).step_by(2).map(|i| u32::from(u16_be(at + i))).sum();
+            assert_eq!((sum & 0xFFFF) + (sum >> 16), 0xFFFF);
+            let source = if direction == Direction::Outgoing { 1 } else { 2 };
+            assert_eq!(bytes[at + 15], source);
+            assert_eq!(bytes[at + 19], 3 - source);
+            assert_eq!(u16_be(at + 24) as usize, 8 + len);
+            at += 28 + len;
+        }
+        assert_eq!(at, bytes.len());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn pcap_record_longer_than_snaplen() {
+        let capture = |snaplen: u32, captured: u32| {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+            bytes.extend_from_slice(&[2, 0, 4, 0]);
+            bytes.extend_from_slice(&[0; 8]);
+            bytes.extend_from_slice(&snaplen.to_le_bytes());
+            bytes.extend_from_slice(&101u32.to_le_bytes());
+            bytes.extend_from_slice(&[0; 8]);
+            bytes.extend_from_slice(&captured.to_le_bytes());
+            bytes.extend_from_slice(&captured.to_le_bytes());
+            bytes
+        };
+
+        // Refused before the record is read, so the missing bytes are never asked for.
+        for (snaplen, captured) in [(1500, 1501), (262_144, 65536), (65535, u32::MAX)] {
+            let bytes = capture(snaplen, captured);
+            let mut reader = PcapReader::new(bytes.as_slice()).unwrap();
+            let error = reader.read_datagram().unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        let bytes = capture(1500, 1500);
+        let mut reader = PcapReader::new(bytes.as_slice()).unwrap();
+        let error = reader.read_datagram().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn replay_capture() {
+        enable_logging();
+
+        let mut time = 100.0;
+        let capture = SharedBuffer::default();
+        let mut one = Endpoint::new(EndpointConfig::new("one"), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two"), time).unwrap();
+        one.set_tap(Box::new(PcapWriter::new(capture.clone()).unwrap()));
+
+        let mut delivered = Vec::new();
+        let mut acked = Vec::new();
+        for i in 0..40 {
+            for packet in one.send(&[0x41; 100]).unwrap() {
+                two.recv(&packet).unwrap();
+            }
+            let size = if i % 10 == 3 { 3000 } else { 50 };
+            let mut replies = two.send(&vec![i as u8; size]).unwrap();
+            if i % 7 == 6 {
+                // Lose a fragment or a whole reply now and then.
+                replies.pop();
+            }
+            replies.reverse();
+            for packet in replies {
+                delivered.extend(one.recv(&packet).unwrap());
+            }
+            acked.extend_from_slice(one.acks());
+            one.clear_acks();
+
+            time += 0.05;
+            one.update(time);
+            two.update(time);
+        }
+        let live_error = one.recv(&[0x01, 0xFF]).unwrap_err();
+
+        let bytes = capture.0.lock().unwrap().clone();
+        let datagrams: Vec<CapturedDatagram> = PcapReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        let report = replay(EndpointConfig::new("replay"), datagrams).unwrap();
+
+        let replayed: Vec<Vec<u8>> = report
+            .events
+            .iter()
+            .flat_map(|event| event.delivered.iter().map(|packet| packet.payload.clone()))
+            .collect();
+        assert_eq!(replayed, delivered);
+        let replayed_acks: Vec<u32> =
+            report.events.iter().flat_map(|event| event.acked.clone()).collect();
+        assert_eq!(replayed_acks, acked);
+        assert!((report.rtt - one.rtt()).abs() < 0.001);
+        let replayed_error = report.events.last().unwrap().error.as_ref().unwrap();
+        assert_eq!(format!("{replayed_error:?}"), format!("{live_error:?}"));
+
+        let (live, replayed) = (one.counters(), report.counters);
+        assert_eq!(replayed.packets_sent, live.packets_sent);
+        assert_eq!(replayed.packets_received, live.packets_received);
+        assert_eq!(replayed.packets_acked, live.packets_acked);
+        assert_eq!(replayed.fragments_received, live.fragments_received);
+        assert_eq!(replayed.fragments_invalid, 1);
+        assert!(report.to_string().contains(&format!("error {live_error:?}")));
+    }
+
+    #[test]
+    fn replay_duplicate_sent() {
+        enable_logging();
+
+        let mut one = Endpoint::new(EndpointConfig::new("one"), 100.0).unwrap();
+        let mut datagrams = Vec::new();
+        for size in [100, 3000] {
+            for packet in one.send(&vec![0x41; size]).unwrap() {
+                let captured = CapturedDatagram {
+                    time: 100.0,
+                    direction: Direction::Outgoing,
+                    datagram: packet,
+                };
+                // Captured twice, as a tap on both ends of a loopback would.
+                datagrams.push(captured.clone());
+                datagrams.push(captured);
+            }
+        }
+
+        let report = replay(EndpointConfig::new("replay"), datagrams).unwrap();
+        assert!(report.events.iter().all(|event| event.error.is_none()));
+        assert_eq!(report.counters.packets_sent, one.counters().packets_sent);
+    }
+
+    /// Records the events it sees as text, after the name and fields of the span they are in.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct EventRecorder {
+        spans: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+        entered: std::sync::Arc<std::sync::Mutex<Vec<usize>>>,
+        events: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    struct FieldText<'a>(&'a mut String);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for FieldText<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn core::fmt::Debug) {
+            use core::fmt::Write;
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for EventRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut text = span.metadata().name().to_string();
+            span.record(&mut FieldText(&mut text));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(text);
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut text = match self.entered.lock().unwrap().last() {
+                Some(&span) => self.spans.lock().unwrap()[span - 1].clone(),
+                None => String::new(),
+            };
+            text.push_str(" |");
+            event.record(&mut FieldText(&mut text));
+            self.events.lock().unwrap().push(text);
+        }
+        fn enter(&self, span: &tracing::span::Id) {
+            self.entered.lock().unwrap().push(span.into_u64() as usize);
+        }
+        fn exit(&self, _: &tracing::span::Id) {
+            self.entered.lock().unwrap().pop();
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "tracing", feature = "std"))]
+    fn tracing_events() {
+        let recorder = EventRecorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let mut config = EndpointConfig::new("one");
+            config.received_packets_buffer_size = 16;
+            let mut one = Endpoint::new(config, 100.0).unwrap();
+            let mut two = Endpoint::new(EndpointConfig::new("two"), 100.0).unwrap();
+            for packet in one.send(&[0x41; 2500]).unwrap() {
+                two.recv(&packet).unwrap();
+            }
+            one.update(100.25);
+            let first = two.send(&[0x42; 10]).unwrap();
+            for packet in &first {
+                one.recv(packet).unwrap();
+            }
+
+            // Once the first packet is stale, it is refused without being reported as received.
+            for _ in 0..16 {
+                for packet in two.send(&[0x42; 10]).unwrap() {
+                    one.recv(&packet).unwrap();
+                }
+            }
+            let seen = recorder.events.lock().unwrap().len();
+            assert!(matches!(
+                one.recv(&first[0]),
+                Err(ReliableError::StalePacket)
+            ));
+            assert_eq!(recorder.events.lock().unwrap().len(), seen);
+        });
+
+        let events = recorder.events.lock().unwrap().join("\n");
+        for expected in [
+            "send endpoint=one sequence=0 size=2500 | message=packet sent datagrams=3",
+            "recv endpoint=two size=1037 | message=fragment received sequence=0 id=0 count=3 \
+             size=1024 received=1",
+            "recv endpoint=two size=457 | message=fragments reassembled sequence=0 size=2500 \
+             fragments=3",
+            "recv endpoint=two size=457 | message=packet received sequence=0 ack=65535 size=2508",
+            "send endpoint=two sequence=0 size=10 | message=packet sent datagrams=1",
+            "recv endpoint=one size=18 | message=packet acked sequence=0 rtt_sample=250.0",
+            "recv endpoint=one size=18 | message=rtt updated rtt=250.0",
+        ] {
+            assert!(
+                events.contains(expected),
+                "missing {:?} in\n{}",
+                expected,
+                events
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn prometheus_metrics() {
+        use std::io::{Read, Write};
+        use std::sync::{Arc, Mutex};
+
+        let mut time = 100.0;
+        let mut one = Endpoint::new(EndpointConfig::new("one"), time).unwrap();
+        let mut two = Endpoint::new(EndpointConfig::new("two \"b\""), time).unwrap();
+        for _ in 0..10 {
+            for packet in one.send(&[0x41; 3000]).unwrap() {
+                two.recv(&packet).unwrap();
+            }
+            for packet in two.send(&[0x42; 10]).unwrap() {
+                one.recv(&packet).unwrap();
+            }
+            time += 0.01;
+            one.update(time);
+            two.update(time);
+        }
+
+        let text = render_prometheus(&[one.metrics(), two.metrics()]);
+        assert!(text.contains("# TYPE reliable_rtt_milliseconds gauge\n"));
+        assert!(text.contains(&format!(
+            "reliable_rtt_milliseconds{{endpoint=\"one\"}} {}\n",
+            one.rtt()
+        )));
+        assert!(text.contains("reliable_bandwidth_kbps{endpoint=\"one\",direction=\"acked\"}"));
+        assert!(text.contains("# TYPE reliable_packets_sent_total counter\n"));
+        assert!(text.contains("reliable_packets_sent_total{endpoint=\"one\"} 10\n"));
+        assert!(text.contains("reliable_fragments_received_total{endpoint=\"two \\\"b\\\"\"} 30\n"));
+        assert_eq!(
+            text.lines()
+                .filter(|line| line.starts_with("reliable_"))
+                .count(),
+            2 * (1 + 1 + 3 + 10)
+        );
+
+        let one = Arc::new(Mutex::new(one));
+        let scraped = one.clone();
+        let server = MetricsServer::bind(0, move || {
+            render_prometheus(&[scraped.lock().unwrap().metrics()])
+        })
+        .unwrap();
+        assert!(server.local_addr().ip().is_loopback());
+
+        let mut stream = std::net::TcpStream::connect(server.local_addr()).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&render_prometheus(&[one.lock().unwrap().metrics()])));
+        drop(server);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn metrics_server_idle_client() {
+        use std::io::{Read, Write};
+        use std::net::TcpStream;
+        use std::time::Instant;
+
+        let server = MetricsServer::bind(0, || "reliable_up 1\n".to_string()).unwrap();
+
+        // Neither a client that never sends its request nor one that never stops sending it
+        // keeps the next scrape waiting for long.
+        let idle = TcpStream::connect(server.local_addr()).unwrap();
+        let mut flooding = TcpStream::connect(server.local_addr()).unwrap();
+        let _ = flooding.write_all(b"GET /metrics HTTP/1.1\r\n");
+        let _ = flooding.write_all(&[b'x'; 16 * 1024]);
+
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("reliable_up 1\n"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // Nor does a client that is still connected hold up the drop.
+        let idle_again = TcpStream::connect(server.local_addr()).unwrap();
+        let start = Instant::now();
+        drop(server);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        drop((idle, flooding, idle_again));
+    }
+
+    #[test]
+    #[cfg(target_has_atomic = "64")]
+    fn virtual_clock() {
+        use crate::VirtualClock;
+
+        // Eleven days in, where f32 seconds only resolve to 62.5ms.
+        let clock = VirtualClock::new(Duration::from_secs(1_000_000));
+        let mut one = Endpoint::with_clock(EndpointConfig::new("one"), clock.clone()).unwrap();
+        let mut two = Endpoint::with_clock(EndpointConfig::new("two"), clock.clone()).unwrap();
+
+        let mut acks = 0;
+        for _ in 0..300 {
+            for packet in one.send(&[0x41; 100]).unwrap() {
+                two.recv(&packet).unwrap();
+            }
+            clock.advance(Duration::from_millis(10));
+            for packet in two.send(&[0x42; 100]).unwrap() {
+                one.recv(&packet).unwrap();
+            }
+            clock.advance(Duration::from_millis(10));
+            acks += one.acks().len();
+            one.clear_acks();
+            one.tick();
+            two.tick();
+        }
+        assert_eq!(acks, 300);
+        assert!((one.rtt() - 10.0).abs() < 0.001);
+        assert!((two.rtt() - 10.0).abs() < 0.001);
+        let (sent, received, acked) = one.bandwidth();
+        assert!(sent > 0.0 && received > 0.0 && acked > 0.0);
+
+        // Virtual clocks never go back, and endpoints on their own clock ignore `update`.
+        clock.set(Duration::from_secs(1));
+        assert_eq!(clock.now(), Duration::from_secs(1_000_006));
+        one.update(5.0);
+        one.update(5.0);
+        assert_eq!(clock.now(), Duration::from_secs(1_000_006));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn instant_clock() {
+        let mut one =
+            Endpoint::with_clock(EndpointConfig::new("one"), InstantClock::new()).unwrap();
+        let mut two =
+            Endpoint::with_clock(EndpointConfig::new("two"), InstantClock::new()).unwrap();
+
+        for packet in one.send(&[0x41; 100]).unwrap() {
+            two.recv(&packet).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        for packet in two.send(&[0x42; 100]).unwrap() {
+            one.recv(&packet).unwrap();
+        }
+        one.tick();
+        assert_eq!(one.acks(), &[0]);
+        assert!(one.rtt() >= 20.0);
+    }
+
+    #[test]
+    fn rust_impl_endpoint() {
+        enable_logging();
+
+        let _endpoint = Endpoint::new(EndpointConfig::new("balls"), 0.0).unwrap();
+    }
+}
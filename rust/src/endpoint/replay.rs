@@ -0,0 +1,163 @@
+//! Replaying captured traffic into a fresh `Endpoint`, to reproduce reassembly and ack bugs
+//! offline.
+//!
+//! Incoming datagrams go through `Endpoint::recv_with_header` at their recorded time. Outgoing
+//! datagrams are not sent again; their headers are recorded as sent at their recorded time, so
+//! the acks in the incoming traffic resolve and the RTT comes out as it did live.
+
+use super::{Endpoint, ReceivedPacket, SentData};
+use crate::{
+    CapturedDatagram, ConfigError, Counters, Direction, EndpointConfig, FragmentHeader, Header,
+    PacketHeader, ReliableError,
+};
+use alloc::vec::Vec;
+use core::fmt;
+use log::*;
+
+/// What replaying one datagram did.
+#[derive(Debug)]
+pub struct ReplayEvent {
+    pub time: f64,
+    pub direction: Direction,
+    pub size: usize,
+    /// Packets the datagram completed.
+    pub delivered: Vec<ReceivedPacket>,
+    /// Extended sequences of the sent packets the datagram acked.
+    pub acked: Vec<u32>,
+    /// Smoothed RTT in milliseconds after the datagram.
+    pub rtt: f32,
+    pub error: Option<ReliableError>,
+}
+
+/// The outcome of `replay`: an event per datagram and the endpoint's state at the end.
+#[derive(Debug)]
+pub struct ReplayReport {
+    pub events: Vec<ReplayEvent>,
+    pub counters: Counters,
+    pub rtt: f32,
+    pub packet_loss: f32,
+    /// Sent, received and acked bandwidth in kbps.
+    pub bandwidth: (f32, f32, f32),
+}
+
+/// Replays `datagrams`, in order, into a fresh endpoint created with `config` at the time of the
+/// first datagram.
+pub fn replay<I>(config: EndpointConfig, datagrams: I) -> Result<ReplayReport, ConfigError>
+where
+    I: IntoIterator<Item = CapturedDatagram>,
+{
+    let mut datagrams = datagrams.into_iter().peekable();
+    let start = datagrams.peek().map_or(0.0, |captured| captured.time);
+    let mut endpoint = Endpoint::new(config, start)?;
+
+    let mut events = Vec::new();
+    for captured in datagrams {
+        endpoint.update(captured.time);
+
+        let mut event = ReplayEvent {
+            time: captured.time,
+            direction: captured.direction,
+            size: captured.datagram.len(),
+            delivered: Vec::new(),
+            acked: Vec::new(),
+            rtt: 0.0,
+            error: None,
+        };
+        let result = match captured.direction {
+            Direction::Outgoing => endpoint.replay_sent(&captured.datagram),
+            Direction::Incoming => endpoint
+                .recv_with_header(&captured.datagram)
+                .map(|delivered| event.delivered = delivered),
+        };
+        if let Err(e) = result {
+            warn!("Replaying datagram at {}: {:?}", captured.time, e);
+            event.error = Some(e);
+        }
+        event.acked = endpoint.acks().to_vec();
+        event.rtt = endpoint.rtt();
+        endpoint.clear_acks();
+        events.push(event);
+    }
+
+    Ok(ReplayReport {
+        events,
+        counters: endpoint.counters(),
+        rtt: endpoint.rtt(),
+        packet_loss: endpoint.packet_loss(),
+        bandwidth: endpoint.bandwidth(),
+    })
+}
+
+impl Endpoint {
+    /// Records a datagram this endpoint sent as though `send` had produced it, without sending
+    /// anything. Fragments of one packet add to the same entry.
+    fn replay_sent(&mut self, datagram: &[u8]) -> Result<(), ReliableError> {
+        let mut reader = crate::Cursor::new(datagram);
+        let (sequence, fragment) = match datagram.first() {
+            None => return Err(ReliableError::PacketTooSmall),
+            Some(prefix) if prefix & 1 == 0 => {
+                (PacketHeader::parse(&mut reader)?.sequence(), false)
+            }
+            Some(_) => (FragmentHeader::parse(&mut reader)?.sequence(), true),
+        };
+
+        if fragment {
+            self.counters.fragments_sent += 1;
+        }
+        if let Some(sent) = self.sent_buffer.get_mut(sequence) {
+            // A packet whose sequence is already recorded was captured twice.
+            if fragment {
+                sent.size += datagram.len();
+            }
+            return Ok(());
+        }
+
+        let extended = if self.counters.packets_sent == 0 {
+            u32::from(sequence)
+        } else {
+            self.extend_sent_sequence(sequence)
+        };
+        let sent = SentData::new(self.time, datagram.len() + self.config.packet_header_size);
+        self.sent_buffer.insert(sent, sequence)?;
+        if self.counters.packets_sent == 0 || extended >= self.sequence {
+            self.sequence = extended.wrapping_add(1);
+        }
+        self.counters.packets_sent += 1;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            let arrow = match event.direction {
+                Direction::Outgoing => "->",
+                Direction::Incoming => "<-",
+            };
+            write!(f, "{:.6} {} {} bytes", event.time, arrow, event.size)?;
+            for packet in &event.delivered {
+                write!(
+                    f,
+                    ", delivered {} ({} bytes)",
+                    packet.sequence,
+                    packet.payload.len()
+                )?;
+            }
+            if !event.acked.is_empty() {
+                write!(f, ", acked {:?}, rtt {:.2}ms", event.acked, event.rtt)?;
+            }
+            if let Some(e) = &event.error {
+                write!(f, ", error {e:?}")?;
+            }
+            writeln!(f)?;
+        }
+
+        let (sent, received, acked) = self.bandwidth;
+        writeln!(
+            f,
+            "rtt {:.2}ms, loss {:.2}%, bandwidth sent {:.2} received {:.2} acked {:.2} kbps",
+            self.rtt, self.packet_loss, sent, received, acked
+        )?;
+        write!(f, "{:?}", self.counters)
+    }
+}
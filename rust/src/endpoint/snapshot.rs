@@ -0,0 +1,419 @@
+//! Serializing an `Endpoint`'s state to bytes and rebuilding it.
+//!
+//! The format is little endian and versioned. It holds whether the endpoint ran on a clock, its
+//! time, RTT, packet loss, bandwidth, counters, sequence and pending acks, and the occupied
+//! entries of the sent, received and reassembly buffers, along with the buffer sizes so a
+//! snapshot is only restored under a config it fits.
+
+use super::{Endpoint, ReassemblyData, RecvData, SentData, TimeSource};
+use crate::{
+    Clock, Counters, EndpointConfig, ReliableError, SequenceBuffer,
+    RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES,
+};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::time::Duration;
+use log::*;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"RLEP";
+const SNAPSHOT_VERSION: u8 = 1;
+
+impl Endpoint {
+    /// Captures the endpoint's state, to be rebuilt with `Endpoint::restore`.
+    ///
+    /// The config is not included; restore with the same one. Pooled buffers are not state and
+    /// are not captured.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::new());
+        w.bytes(SNAPSHOT_MAGIC);
+        w.u8(SNAPSHOT_VERSION);
+        w.len(self.config.sent_packets_buffer_size);
+        w.len(self.config.received_packets_buffer_size);
+        w.len(self.config.fragment_reassembly_buffer_size);
+        w.len(self.config.fragment_size);
+
+        w.u8(match self.time_source {
+            TimeSource::Update => 0,
+            TimeSource::Clock { .. } => 1,
+        });
+        w.duration(self.time);
+        w.f32(self.rtt);
+        w.f32(self.packet_loss);
+        w.f32(self.sent_bandwidth_kbps);
+        w.f32(self.received_bandwidth_kbps);
+        w.f32(self.acked_bandwidth_kbps);
+        w.counters(&self.counters);
+        w.u32(self.sequence);
+        match self.latest_received {
+            Some(latest) => {
+                w.u8(1);
+                w.u32(latest);
+            }
+            None => w.u8(0),
+        }
+        w.len(self.acks.len());
+        for &ack in &self.acks {
+            w.u32(ack);
+        }
+
+        w.window(&self.sent_buffer);
+        w.len(self.sent_buffer.occupied());
+        for (sequence, sent) in self.sent_buffer.iter() {
+            w.u16(sequence);
+            w.duration(sent.time);
+            w.u8(u8::from(sent.acked));
+            w.len(sent.size);
+        }
+
+        w.window(&self.recv_buffer);
+        w.len(self.recv_buffer.occupied());
+        for (sequence, received) in self.recv_buffer.iter() {
+            w.u16(sequence);
+            w.duration(received.time);
+            w.len(received.size);
+        }
+
+        w.window(&self.reassembly_buffer);
+        w.len(self.reassembly_buffer.occupied());
+        for (sequence, reassembly) in self.reassembly_buffer.iter() {
+            w.u16(sequence);
+            w.u16(reassembly.ack);
+            w.u64(reassembly.ack_bits);
+            w.len(reassembly.num_fragments_received);
+            w.len(reassembly.num_fragments_total);
+            w.len(reassembly.packet_bytes);
+            w.len(reassembly.header_size);
+            // The bitset is kept in bytes but stored as 64-bit words.
+            w.len(reassembly.num_fragments_total.div_ceil(64));
+            for word in reassembly.fragments_received.chunks(8) {
+                let mut bits = [0; 8];
+                bits[..word.len()].copy_from_slice(word);
+                w.u64(u64::from_le_bytes(bits));
+            }
+            w.len(reassembly.buffer.len());
+            w.bytes(&reassembly.buffer);
+        }
+
+        w.0
+    }
+
+    /// Rebuilds an endpoint from `Endpoint::snapshot`, under the config it was taken with.
+    ///
+    /// The endpoint's time starts at the snapshot's and moves on `update`, as if made by `new`.
+    /// Snapshots that are malformed, whose buffer sizes differ from `config` or that were taken
+    /// of an endpoint made by `with_clock` are refused with `ReliableError::InvalidSnapshot`.
+    pub fn restore(config: EndpointConfig, snapshot: &[u8]) -> Result<Self, ReliableError> {
+        let mut endpoint = Self::new(config, 0.0)?;
+        endpoint.read_snapshot(&mut Reader { bytes: snapshot })?;
+        Ok(endpoint)
+    }
+
+    /// Like `restore`, for snapshots of endpoints made by `with_clock`. The endpoint reads its
+    /// time from `clock` again, which should carry on from the time the snapshot was taken at.
+    pub fn restore_with_clock<C>(
+        config: EndpointConfig,
+        clock: C,
+        snapshot: &[u8],
+    ) -> Result<Self, ReliableError>
+    where
+        C: Clock + Send + 'static,
+    {
+        let mut endpoint = Self::with_clock(config, clock)?;
+        endpoint.read_snapshot(&mut Reader { bytes: snapshot })?;
+        Ok(endpoint)
+    }
+
+    fn read_snapshot(&mut self, r: &mut Reader<'_>) -> Result<(), ReliableError> {
+        if r.bytes(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            error!("Snapshot has an unknown magic");
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        let version = r.u8()?;
+        if version != SNAPSHOT_VERSION {
+            error!("Snapshot has unknown version {version}");
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        let sizes = [r.len()?, r.len()?, r.len()?, r.len()?];
+        if sizes
+            != [
+                self.config.sent_packets_buffer_size,
+                self.config.received_packets_buffer_size,
+                self.config.fragment_reassembly_buffer_size,
+                self.config.fragment_size,
+            ]
+        {
+            error!("Snapshot was taken with different buffer sizes: {sizes:?}");
+            return Err(ReliableError::InvalidSnapshot);
+        }
+
+        let on_clock = match r.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(ReliableError::InvalidSnapshot),
+        };
+        if on_clock != matches!(self.time_source, TimeSource::Clock { .. }) {
+            error!(
+                "Snapshot was taken of an endpoint {} a clock",
+                if on_clock { "on" } else { "without" }
+            );
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        self.time = r.duration()?;
+        self.rtt = r.f32()?;
+        self.packet_loss = r.f32()?;
+        self.sent_bandwidth_kbps = r.f32()?;
+        self.received_bandwidth_kbps = r.f32()?;
+        self.acked_bandwidth_kbps = r.f32()?;
+        self.counters = r.counters()?;
+        self.sequence = r.u32()?;
+        self.latest_received = match r.u8()? {
+            0 => None,
+            1 => Some(r.u32()?),
+            _ => return Err(ReliableError::InvalidSnapshot),
+        };
+        let num_acks = r.len_at_most(self.config.ack_buffer_size)?;
+        self.acks.clear();
+        for _ in 0..num_acks {
+            self.acks.push(r.u32()?);
+        }
+
+        let (sequence, wraps) = r.window()?;
+        self.sent_buffer.reset_to(sequence, wraps);
+        for _ in 0..r.len_at_most(self.sent_buffer.len())? {
+            let sequence = r.u16()?;
+            let sent = SentData {
+                time: r.duration()?,
+                acked: r.u8()? != 0,
+                size: r.len()?,
+            };
+            restore_entry(&mut self.sent_buffer, sequence, sent)?;
+        }
+
+        let (sequence, wraps) = r.window()?;
+        self.recv_buffer.reset_to(sequence, wraps);
+        for _ in 0..r.len_at_most(self.recv_buffer.len())? {
+            let sequence = r.u16()?;
+            let received = RecvData {
+                time: r.duration()?,
+                size: r.len()?,
+            };
+            restore_entry(&mut self.recv_buffer, sequence, received)?;
+        }
+
+        let (sequence, wraps) = r.window()?;
+        self.reassembly_buffer.reset_to(sequence, wraps);
+        for _ in 0..r.len_at_most(self.reassembly_buffer.len())? {
+            let sequence = r.u16()?;
+            let reassembly = self.read_reassembly(sequence, r)?;
+            restore_entry(&mut self.reassembly_buffer, sequence, reassembly)?;
+        }
+
+        if !r.bytes.is_empty() {
+            error!("Snapshot has {} trailing bytes", r.bytes.len());
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        Ok(())
+    }
+
+    fn read_reassembly(
+        &mut self,
+        sequence: u16,
+        r: &mut Reader<'_>,
+    ) -> Result<ReassemblyData, ReliableError> {
+        let ack = r.u16()?;
+        let ack_bits = r.u64()?;
+        let num_fragments_received = r.len()?;
+        let num_fragments_total = r.len_at_most(self.config.max_fragments as usize)?;
+        let packet_bytes = r.len()?;
+        let header_size = r.len_at_most(RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES)?;
+
+        let mut reassembly = ReassemblyData::new(
+            sequence,
+            num_fragments_total,
+            self.config.fragment_size,
+            self.buffer_pool.acquire(),
+            self.buffer_pool.acquire(),
+        );
+        if num_fragments_total == 0
+            || num_fragments_received > num_fragments_total
+            || packet_bytes > num_fragments_total * self.config.fragment_size
+            || r.len()? != num_fragments_total.div_ceil(64)
+        {
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        let mut received = 0;
+        for (index, word) in reassembly.fragments_received.chunks_mut(8).enumerate() {
+            let bits = r.u64()?;
+            let fragments = num_fragments_total - index * 64;
+            if fragments < 64 && bits >> fragments != 0 {
+                error!("Snapshot marks fragments past the end of packet {sequence} as received");
+                return Err(ReliableError::InvalidSnapshot);
+            }
+            received += bits.count_ones() as usize;
+            word.copy_from_slice(&bits.to_le_bytes()[..word.len()]);
+        }
+        if received != num_fragments_received {
+            error!(
+                "Snapshot counts {num_fragments_received} fragments of packet {sequence} received, \
+                 but marks {received}"
+            );
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        let buffer_len = reassembly.buffer.len();
+        if r.len()? != buffer_len {
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        reassembly.buffer.copy_from_slice(r.bytes(buffer_len)?);
+
+        reassembly.ack = ack;
+        reassembly.ack_bits = ack_bits;
+        reassembly.num_fragments_received = num_fragments_received;
+        reassembly.packet_bytes = packet_bytes;
+        reassembly.header_size = header_size;
+        Ok(reassembly)
+    }
+}
+
+/// Puts a snapshot entry back, refusing entries outside of the restored window.
+fn restore_entry<T>(
+    buffer: &mut SequenceBuffer<T>,
+    sequence: u16,
+    entry: T,
+) -> Result<(), ReliableError>
+where
+    T: Default + Clone + Send + Sync,
+{
+    let window = buffer.sequence();
+    if buffer.insert(entry, sequence).is_err() || buffer.sequence() != window {
+        error!("Snapshot entry {sequence} is outside of its buffer");
+        return Err(ReliableError::InvalidSnapshot);
+    }
+    Ok(())
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+    fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+    /// Where a buffer's window ends and how often it has wrapped to get there.
+    #[allow(clippy::cast_sign_loss)]
+    fn window<T>(&mut self, buffer: &SequenceBuffer<T>)
+    where
+        T: Default + Clone + Send + Sync,
+    {
+        self.u16(buffer.sequence());
+        self.u64(buffer.wraps() as u64);
+    }
+    fn duration(&mut self, value: Duration) {
+        self.u64(value.as_secs());
+        self.u32(value.subsec_nanos());
+    }
+    fn counters(&mut self, counters: &Counters) {
+        self.u64(counters.packets_sent);
+        self.u64(counters.packets_received);
+        self.u64(counters.packets_acked);
+        self.u64(counters.packets_stale);
+        self.u64(counters.packets_invalid);
+        self.u64(counters.packets_too_large_to_send);
+        self.u64(counters.packets_too_large_to_receive);
+        self.u64(counters.fragments_sent);
+        self.u64(counters.fragments_received);
+        self.u64(counters.fragments_invalid);
+    }
+    /// Sizes are stored as `u32` so snapshots move between 32 and 64-bit hosts.
+    #[allow(clippy::cast_possible_truncation)]
+    fn len(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ReliableError> {
+        if self.bytes.len() < len {
+            error!("Snapshot is truncated");
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReliableError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+    fn u8(&mut self) -> Result<u8, ReliableError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, ReliableError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+    fn u32(&mut self) -> Result<u32, ReliableError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    fn u64(&mut self) -> Result<u64, ReliableError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    fn f32(&mut self) -> Result<f32, ReliableError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+    fn window(&mut self) -> Result<(u16, i64), ReliableError> {
+        let sequence = self.u16()?;
+        let wraps = i64::try_from(self.u64()?).map_err(|_| ReliableError::InvalidSnapshot)?;
+        Ok((sequence, wraps))
+    }
+    fn duration(&mut self) -> Result<Duration, ReliableError> {
+        let secs = self.u64()?;
+        let nanos = self.u32()?;
+        if nanos >= 1_000_000_000 {
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+    fn counters(&mut self) -> Result<Counters, ReliableError> {
+        Ok(Counters {
+            packets_sent: self.u64()?,
+            packets_received: self.u64()?,
+            packets_acked: self.u64()?,
+            packets_stale: self.u64()?,
+            packets_invalid: self.u64()?,
+            packets_too_large_to_send: self.u64()?,
+            packets_too_large_to_receive: self.u64()?,
+            fragments_sent: self.u64()?,
+            fragments_received: self.u64()?,
+            fragments_invalid: self.u64()?,
+        })
+    }
+    fn len(&mut self) -> Result<usize, ReliableError> {
+        usize::try_from(self.u32()?).map_err(|_| ReliableError::InvalidSnapshot)
+    }
+    fn len_at_most(&mut self, max: usize) -> Result<usize, ReliableError> {
+        let len = self.len()?;
+        if len > max {
+            error!("Snapshot length {len} exceeds {max}");
+            return Err(ReliableError::InvalidSnapshot);
+        }
+        Ok(len)
+    }
+}
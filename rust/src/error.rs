@@ -1,34 +1,133 @@
+use crate::FragmentFormat;
+use alloc::string::String;
+
 #[derive(Debug)]
-#[cfg_attr(feature = "cargo-clippy", allow(stutter))]
+#[allow(clippy::module_name_repetitions)]
 pub enum ReliableError {
+    #[cfg(feature = "std")]
     Io(std::io::Error),
     ExceededMaxPacketSize,
+    ExceededMaxFragments,
     SequenceBufferFull,
     PacketTooSmall,
     InvalidPacket,
     StalePacket,
     InvalidFragment,
+    InvalidAppHeader,
+    InvalidSnapshot,
+    Config(ConfigError),
 }
 
-impl std::fmt::Display for ReliableError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "invalid first item to double")
+impl core::fmt::Display for ReliableError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            ReliableError::Io(e) => write!(f, "i/o error: {e}"),
+            ReliableError::ExceededMaxPacketSize => {
+                write!(f, "packet is larger than max_packet_size")
+            }
+            ReliableError::ExceededMaxFragments => {
+                write!(f, "packet needs more than max_fragments fragments")
+            }
+            ReliableError::SequenceBufferFull => write!(f, "sequence buffer is full"),
+            ReliableError::PacketTooSmall => write!(f, "packet is too small"),
+            ReliableError::InvalidPacket => write!(f, "invalid packet"),
+            ReliableError::StalePacket => write!(f, "stale packet"),
+            ReliableError::InvalidFragment => write!(f, "invalid fragment"),
+            ReliableError::InvalidAppHeader => write!(f, "invalid application header"),
+            ReliableError::InvalidSnapshot => write!(f, "invalid snapshot"),
+            ReliableError::Config(e) => write!(f, "invalid config: {e}"),
+        }
     }
 }
 
 // This is important for other errors to wrap this one.
+#[cfg(feature = "std")]
 impl std::error::Error for ReliableError {
-    fn description(&self) -> &str {
-        "invalid first item to double"
-    }
-
-    fn cause(&self) -> Option<&dyn std::error::Error> {
-        None
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReliableError::Io(e) => Some(e),
+            ReliableError::Config(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for ReliableError {
     fn from(err: std::io::Error) -> Self {
         ReliableError::Io(err)
     }
 }
+
+impl From<ConfigError> for ReliableError {
+    fn from(err: ConfigError) -> Self {
+        ReliableError::Config(err)
+    }
+}
+
+/// An `EndpointConfig` invariant that does not hold.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    ZeroSize(&'static str),
+    FragmentSizeExceedsMaxPacketSize {
+        fragment_size: usize,
+        max_packet_size: usize,
+    },
+    TooFewFragments {
+        max_fragments: u32,
+        fragment_size: usize,
+        max_packet_size: usize,
+    },
+    TooManyFragmentsForFormat {
+        max_fragments: u32,
+        format: FragmentFormat,
+    },
+    SmoothingFactorOutOfRange(&'static str, f32),
+    Io(String),
+    Parse(String),
+    /// A C `Config` already has allocator functions where counting ones were to be installed.
+    AllocatorAlreadySet,
+}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConfigError::ZeroSize(field) => write!(f, "{field} must be greater than zero"),
+            ConfigError::FragmentSizeExceedsMaxPacketSize {
+                fragment_size,
+                max_packet_size,
+            } => write!(
+                f,
+                "fragment_size {fragment_size} is larger than max_packet_size {max_packet_size}"
+            ),
+            ConfigError::TooFewFragments {
+                max_fragments,
+                fragment_size,
+                max_packet_size,
+            } => write!(
+                f,
+                "max_fragments {max_fragments} of fragment_size {fragment_size} cannot carry \
+                 max_packet_size {max_packet_size}"
+            ),
+            ConfigError::TooManyFragmentsForFormat {
+                max_fragments,
+                format,
+            } => write!(
+                f,
+                "max_fragments {max_fragments} exceeds the {} fragments the {format:?} fragment \
+                 format can encode",
+                format.max_fragments()
+            ),
+            ConfigError::SmoothingFactorOutOfRange(field, factor) => {
+                write!(f, "{field} {factor} is outside of (0, 1]")
+            }
+            ConfigError::Io(e) => write!(f, "could not read config: {e}"),
+            ConfigError::Parse(e) => write!(f, "could not parse config: {e}"),
+            ConfigError::AllocatorAlreadySet => write!(f, "config already has allocator functions"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConfigError {}
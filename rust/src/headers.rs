@@ -1,41 +1,126 @@
-use crate::ReliableError;
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::{Cursor, ReliableError};
+use byteorder::LittleEndian;
+#[cfg(feature = "std")]
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use core::num::Wrapping;
 use log::*;
-use std::num::Wrapping;
 
 pub trait HeaderParser {
     type T;
 
     fn size(&self) -> usize;
-    fn write(&self, writer: &mut std::io::Cursor<&mut [u8]>) -> Result<(), ReliableError>;
-    fn parse(reader: &mut std::io::Cursor<&[u8]>) -> Result<Self::T, ReliableError>;
+    fn write(&self, writer: &mut Cursor<&mut [u8]>) -> Result<(), ReliableError>;
+    fn parse(reader: &mut Cursor<&[u8]>) -> Result<Self::T, ReliableError>;
+}
+
+/// How application header bytes following a `PacketHeader` are framed on the wire.
+///
+/// Signalled by bit 6 (present) and bit 7 (length prefixed) of the prefix byte, or by bits 4 and
+/// 5 of the extension byte in wide ack headers.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AppHeaderFraming {
+    Fixed,
+    LengthPrefixed,
+}
+
+/// Width of the ack bitfield carried in `PacketHeader`.
+///
+/// `Narrow` is the original 32 packet window. `Wide` acks 64 packets and is flagged by setting
+/// bit 7 of the prefix byte without bit 6, which is followed by an extension byte: bits 0-3 flag
+/// which of ack bytes 4-7 are sent (bytes equal to 0xFF are elided, as for bytes 0-3) and bits 4
+/// and 5 take over the app header flags. Receivers accept both.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AckFormat {
+    #[default]
+    Narrow,
+    Wide,
+}
+
+impl AckFormat {
+    /// Number of packets acknowledged by one header, counting back from `ack`.
+    pub fn bits(self) -> u16 {
+        match self {
+            AckFormat::Narrow => 32,
+            AckFormat::Wide => 64,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, PartialOrd, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PacketHeader {
     sequence: u16,
     ack: u16,
-    ack_bits: u32,
+    ack_bits: u64,
+    ack_format: AckFormat,
+    app_header: Option<AppHeaderFraming>,
 }
 
 impl PacketHeader {
     pub fn new(sequence: u16, ack: u16, ack_bits: u32) -> Self {
+        Self {
+            sequence,
+            ack,
+            ack_bits: u64::from(ack_bits),
+            ack_format: AckFormat::Narrow,
+            app_header: None,
+        }
+    }
+
+    /// A header acknowledging the 64 packets up to and including `ack`.
+    pub fn new_wide(sequence: u16, ack: u16, ack_bits: u64) -> Self {
         Self {
             sequence,
             ack,
             ack_bits,
+            ack_format: AckFormat::Wide,
+            app_header: None,
         }
     }
 
+    #[must_use]
+    pub fn with_app_header(mut self, framing: AppHeaderFraming) -> Self {
+        self.app_header = Some(framing);
+        self
+    }
+
+    pub fn app_header(&self) -> Option<AppHeaderFraming> {
+        self.app_header
+    }
+
     pub fn sequence(&self) -> u16 {
         self.sequence
     }
     pub fn ack(&self) -> u16 {
         self.ack
     }
+    /// The 32 packets up to and including `ack`. Wide headers ack 64, see `wide_ack_bits`.
+    #[allow(clippy::cast_possible_truncation)]
     pub fn ack_bits(&self) -> u32 {
+        self.ack_bits as u32
+    }
+    /// The packets up to and including `ack`, 32 or 64 of them depending on `ack_format`.
+    pub fn wide_ack_bits(&self) -> u64 {
         self.ack_bits
     }
+    pub fn ack_format(&self) -> AckFormat {
+        self.ack_format
+    }
+
+    /// Bit mask of the ack bytes 4-7 that are sent, as flagged in bits 0-3 of the extension byte.
+    fn wide_ack_bytes(&self) -> u8 {
+        let mut flags = 0;
+        for i in 0..4 {
+            let mask = 0xFF_u64 << (32 + 8 * i);
+            if (self.ack_bits & mask) != mask {
+                flags |= 1 << i;
+            }
+        }
+        flags
+    }
 }
 
 impl HeaderParser for PacketHeader {
@@ -69,14 +154,15 @@ impl HeaderParser for PacketHeader {
             size += 1;
         }
 
+        if self.ack_format == AckFormat::Wide {
+            size += 1 + self.wide_ack_bytes().count_ones() as usize;
+        }
+
         size
     }
 
-    #[cfg_attr(
-        feature = "cargo-clippy",
-        allow(cast_possible_truncation, cast_sign_loss, if_not_else)
-    )]
-    fn write(&self, writer: &mut std::io::Cursor<&mut [u8]>) -> Result<(), ReliableError> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::if_not_else)]
+    fn write(&self, writer: &mut Cursor<&mut [u8]>) -> Result<(), ReliableError> {
         let mut prefix_byte = 0;
 
         if (self.ack_bits & 0x0000_00FF) != 0x0000_00FF {
@@ -104,7 +190,21 @@ impl HeaderParser for PacketHeader {
             prefix_byte |= 1 << 5;
         }
 
-        writer.write_u8(prefix_byte)?;
+        let app_header_flags = match self.app_header {
+            Some(AppHeaderFraming::Fixed) => 1,
+            Some(AppHeaderFraming::LengthPrefixed) => 1 | 2,
+            None => 0,
+        };
+
+        match self.ack_format {
+            AckFormat::Narrow => {
+                writer.write_u8(prefix_byte | (app_header_flags << 6))?;
+            }
+            AckFormat::Wide => {
+                writer.write_u8(prefix_byte | (1 << 7))?;
+                writer.write_u8(self.wide_ack_bytes() | (app_header_flags << 4))?;
+            }
+        }
         writer.write_u16::<LittleEndian>(self.sequence)?;
 
         if sequence_difference <= 255 {
@@ -129,14 +229,20 @@ impl HeaderParser for PacketHeader {
             writer.write_u8(((self.ack_bits & 0xFF00_0000) >> 24) as u8)?;
         }
 
+        if self.ack_format == AckFormat::Wide {
+            let wide_ack_bytes = self.wide_ack_bytes();
+            for i in 0..4 {
+                if wide_ack_bytes & (1 << i) != 0 {
+                    writer.write_u8((self.ack_bits >> (32 + 8 * i)) as u8)?;
+                }
+            }
+        }
+
         Ok(())
     }
 
-    #[cfg_attr(
-        feature = "cargo-clippy",
-        allow(cast_possible_truncation, cast_sign_loss, if_not_else)
-    )]
-    fn parse(reader: &mut std::io::Cursor<&[u8]>) -> Result<Self, ReliableError> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::if_not_else)]
+    fn parse(reader: &mut Cursor<&[u8]>) -> Result<Self, ReliableError> {
         let packet = *(reader.get_ref());
 
         if packet.len() < 3 {
@@ -150,24 +256,54 @@ impl HeaderParser for PacketHeader {
             return Err(ReliableError::InvalidPacket);
         }
 
-        let ack: u16;
-        let mut ack_bits: u32 = 0xFFFF_FFFF;
+        // Bit 7 without bit 6 announces the wide ack extension byte.
+        let (ack_format, extension_byte) = if prefix_byte & (3 << 6) == 1 << 7 {
+            if packet.len() < 4 {
+                error!("Packet too small for wide ack packet header");
+                return Err(ReliableError::PacketTooSmall);
+            }
+            let extension_byte = reader.read_u8()?;
+            if extension_byte & (3 << 6) != 0 {
+                error!("extension byte sets reserved bits");
+                return Err(ReliableError::InvalidPacket);
+            }
+            (AckFormat::Wide, extension_byte)
+        } else {
+            (AckFormat::Narrow, 0)
+        };
+
+        let app_header_flags = match ack_format {
+            AckFormat::Narrow => prefix_byte >> 6,
+            AckFormat::Wide => (extension_byte >> 4) & 3,
+        };
+        let app_header = match (app_header_flags & 1 != 0, app_header_flags & 2 != 0) {
+            (false, false) => None,
+            (true, false) => Some(AppHeaderFraming::Fixed),
+            (true, true) => Some(AppHeaderFraming::LengthPrefixed),
+            (false, true) => {
+                error!("header flags a length prefix without an app header");
+                return Err(ReliableError::InvalidPacket);
+            }
+        };
+
+        let mut ack_bits: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+        let header_start = reader.position() as usize;
         let sequence = reader.read_u16::<LittleEndian>()?;
 
-        if prefix_byte & (1 << 5) != 0 {
-            if packet.len() < 4 {
+        let ack = if prefix_byte & (1 << 5) != 0 {
+            if packet.len() < header_start + 3 {
                 error!("Packet too small for packet header (2)");
                 return Err(ReliableError::InvalidPacket);
             }
             let sequence_difference = reader.read_u8()?;
-            ack = (Wrapping(sequence) - Wrapping(u16::from(sequence_difference))).0;
+            (Wrapping(sequence) - Wrapping(u16::from(sequence_difference))).0
         } else {
-            if packet.len() < 5 {
+            if packet.len() < header_start + 4 {
                 error!("Packet too small for packet header (3)");
                 return Err(ReliableError::InvalidPacket);
             }
-            ack = reader.read_u16::<LittleEndian>()?;
-        }
+            reader.read_u16::<LittleEndian>()?
+        };
 
         let mut expected_bytes: usize = 0;
         for i in 1..5 {
@@ -175,61 +311,130 @@ impl HeaderParser for PacketHeader {
                 expected_bytes += 1;
             }
         }
+        expected_bytes += (extension_byte & 0xF).count_ones() as usize;
         if packet.len() < reader.position() as usize + expected_bytes {
             error!("Packet too small for packet header (4)");
             return Err(ReliableError::InvalidPacket);
         }
 
         if prefix_byte & (1 << 1) != 0 {
-            ack_bits &= 0xFFFF_FF00;
-            ack_bits |= u32::from(reader.read_u8()?);
+            ack_bits &= 0xFFFF_FFFF_FFFF_FF00;
+            ack_bits |= u64::from(reader.read_u8()?);
         }
 
         if prefix_byte & (1 << 2) != 0 {
-            ack_bits &= 0xFFFF_00FF;
-            ack_bits |= u32::from(reader.read_u8()?) << 8;
+            ack_bits &= 0xFFFF_FFFF_FFFF_00FF;
+            ack_bits |= u64::from(reader.read_u8()?) << 8;
         }
 
         if prefix_byte & (1 << 3) != 0 {
-            ack_bits &= 0xFF00_FFFF;
-            ack_bits |= u32::from(reader.read_u8()?) << 16;
+            ack_bits &= 0xFFFF_FFFF_FF00_FFFF;
+            ack_bits |= u64::from(reader.read_u8()?) << 16;
         }
 
         if prefix_byte & (1 << 4) != 0 {
-            ack_bits &= 0x00FF_FFFF;
-            ack_bits |= u32::from(reader.read_u8()?) << 24;
+            ack_bits &= 0xFFFF_FFFF_00FF_FFFF;
+            ack_bits |= u64::from(reader.read_u8()?) << 24;
+        }
+
+        match ack_format {
+            AckFormat::Narrow => ack_bits &= 0xFFFF_FFFF,
+            AckFormat::Wide => {
+                for i in 0..4 {
+                    if extension_byte & (1 << i) != 0 {
+                        let shift = 32 + 8 * i;
+                        ack_bits &= !(0xFF << shift);
+                        ack_bits |= u64::from(reader.read_u8()?) << shift;
+                    }
+                }
+            }
         }
 
         Ok(Self {
             sequence,
             ack,
             ack_bits,
+            ack_format,
+            app_header,
         })
     }
 }
 
+/// Wire layout used for fragment ids and counts.
+///
+/// `Narrow` is the original 5 byte header with `u8` ids, which caps a packet at 255 fragments.
+/// `Wide` uses `u16` ids and is flagged by bit 1 of the prefix byte. Receivers accept both.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FragmentFormat {
+    #[default]
+    Narrow,
+    Wide,
+}
+
+impl FragmentFormat {
+    pub fn header_size(self) -> usize {
+        match self {
+            FragmentFormat::Narrow => crate::RELIABLE_FRAGMENT_HEADER_BYTES,
+            FragmentFormat::Wide => crate::RELIABLE_WIDE_FRAGMENT_HEADER_BYTES,
+        }
+    }
+
+    /// Most fragments one packet can be split into: 255 for `Narrow`, 65535 for `Wide`.
+    pub fn max_fragments(self) -> usize {
+        match self {
+            FragmentFormat::Narrow => usize::from(u8::MAX),
+            FragmentFormat::Wide => usize::from(u16::MAX),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, PartialOrd, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FragmentHeader {
     sequence: u16,
-    id: u8,
-    num_fragments: u8, // TODO: wouldnt it be more efficient for this to be remaining?
+    id: u16,
+    num_fragments: u16, // TODO: wouldnt it be more efficient for this to be remaining?
+    wide: bool,
     packet_header: Option<PacketHeader>,
 }
 
-impl<'a> FragmentHeader {
+impl FragmentHeader {
     pub fn new(id: u8, num_fragments: u8, packet_header: PacketHeader) -> Self {
+        let sequence = packet_header.sequence();
+        Self {
+            id: u16::from(id),
+            num_fragments: u16::from(num_fragments),
+            wide: false,
+            packet_header: Some(packet_header),
+            sequence,
+        }
+    }
+    pub fn new_fragment(id: u8, num_fragments: u8, sequence: u16) -> Self {
+        Self {
+            id: u16::from(id),
+            num_fragments: u16::from(num_fragments),
+            wide: false,
+            sequence,
+            packet_header: None,
+        }
+    }
+    pub fn new_wide(id: u16, num_fragments: u16, packet_header: PacketHeader) -> Self {
         let sequence = packet_header.sequence();
         Self {
             id,
             num_fragments,
+            wide: true,
             packet_header: Some(packet_header),
             sequence,
         }
     }
-    pub fn new_fragment(id: u8, num_fragments: u8, sequence: u16) -> Self {
+    pub fn new_wide_fragment(id: u16, num_fragments: u16, sequence: u16) -> Self {
         Self {
             id,
             num_fragments,
+            wide: true,
             sequence,
             packet_header: None,
         }
@@ -238,12 +443,19 @@ impl<'a> FragmentHeader {
     pub fn sequence(&self) -> u16 {
         self.sequence
     }
-    pub fn id(&self) -> u8 {
+    pub fn id(&self) -> u16 {
         self.id
     }
-    pub fn count(&self) -> u8 {
+    pub fn count(&self) -> u16 {
         self.num_fragments
     }
+    pub fn format(&self) -> FragmentFormat {
+        if self.wide {
+            FragmentFormat::Wide
+        } else {
+            FragmentFormat::Narrow
+        }
+    }
     pub fn packet_header(&self) -> Option<&PacketHeader> {
         self.packet_header.as_ref()
     }
@@ -253,25 +465,39 @@ impl HeaderParser for FragmentHeader {
     type T = Self;
 
     fn size(&self) -> usize {
+        let base = self.format().header_size();
         if self.id == 0 {
-            if self.packet_header.is_some() {
-                return self.packet_header.as_ref().unwrap().size() + 5;
+            if let Some(packet_header) = &self.packet_header {
+                return packet_header.size() + base;
             }
             panic!("Attemtping to retrieve size on a 0 ID packet with no packet header");
         } else {
-            5
+            base
         }
     }
 
-    fn write(&self, writer: &mut std::io::Cursor<&mut [u8]>) -> Result<(), ReliableError> {
-        writer.write_u8(1)?;
-        writer.write_u16::<LittleEndian>(self.sequence)?;
-        writer.write_u8(self.id)?;
-        writer.write_u8(self.num_fragments)?;
+    #[allow(clippy::cast_possible_truncation)]
+    fn write(&self, writer: &mut Cursor<&mut [u8]>) -> Result<(), ReliableError> {
+        if self.wide {
+            writer.write_u8(1 | (1 << 1))?;
+            writer.write_u16::<LittleEndian>(self.sequence)?;
+            writer.write_u16::<LittleEndian>(self.id)?;
+            writer.write_u16::<LittleEndian>(self.num_fragments)?;
+        } else {
+            if usize::from(self.num_fragments) > FragmentFormat::Narrow.max_fragments()
+                || self.id >= self.num_fragments
+            {
+                return Err(ReliableError::InvalidFragment);
+            }
+            writer.write_u8(1)?;
+            writer.write_u16::<LittleEndian>(self.sequence)?;
+            writer.write_u8(self.id as u8)?;
+            writer.write_u8(self.num_fragments as u8)?;
+        }
 
         if self.id == 0 {
-            if self.packet_header.is_some() {
-                self.packet_header.as_ref().unwrap().write(writer)?;
+            if let Some(packet_header) = &self.packet_header {
+                packet_header.write(writer)?;
             } else {
                 return Err(ReliableError::InvalidFragment);
             }
@@ -280,22 +506,34 @@ impl HeaderParser for FragmentHeader {
         Ok(())
     }
 
-    fn parse(reader: &mut std::io::Cursor<&[u8]>) -> Result<Self::T, ReliableError> {
-        //let packet_header = PacketHeader::default();
-
+    fn parse(reader: &mut Cursor<&[u8]>) -> Result<Self::T, ReliableError> {
         let prefix_byte = reader.read_u8()?;
-        if prefix_byte != 1 {
-            panic!("Not a fragment packet");
+        if prefix_byte & !(1 << 1) != 1 {
+            error!("prefix byte does not indicate fragment packet");
+            return Err(ReliableError::InvalidPacket);
         }
+        let wide = prefix_byte & (1 << 1) != 0;
 
         let sequence = reader.read_u16::<LittleEndian>()?;
-        let id = reader.read_u8()?;
-        let num_fragments = reader.read_u8()?;
+        let (id, num_fragments) = if wide {
+            (
+                reader.read_u16::<LittleEndian>()?,
+                reader.read_u16::<LittleEndian>()?,
+            )
+        } else {
+            (u16::from(reader.read_u8()?), u16::from(reader.read_u8()?))
+        };
+
+        if id >= num_fragments {
+            error!("fragment id {} outside of range of num fragments {}", id, num_fragments);
+            return Err(ReliableError::InvalidFragment);
+        }
 
         let mut r = Self {
             sequence,
             id,
             num_fragments,
+            wide,
             packet_header: None,
         };
 
@@ -1,34 +1,92 @@
-#![cfg_attr(
-feature = "cargo-clippy",
-warn(clippy, clippy_correctness, clippy_style, clippy_pedantic, clippy_perf)
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![warn(clippy::all, clippy::correctness, clippy::style, clippy::pedantic, clippy::perf)]
+#![allow(
+    clippy::similar_names,
+    clippy::must_use_candidate,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::wildcard_imports
 )]
-#![cfg_attr(feature = "cargo-clippy", allow(similar_names))]
 #![warn(rust_2018_idioms)]
 // TODO: remove when done
 #![allow(dead_code, unused_imports)]
 
-use log::*;
+extern crate alloc;
 
-#[macro_use]
-use std::num::Wrapping;
 
+#[cfg(feature = "c-backend")]
 pub mod binding_version;
+#[cfg(feature = "c-backend")]
 pub mod capi;
 
+mod backend;
+
+pub use crate::backend::Counters;
+pub use crate::backend::ReliableEndpoint;
+
+mod capture;
+
+pub use crate::capture::CapturedDatagram;
+pub use crate::capture::Direction;
+pub use crate::capture::PacketTap;
+#[cfg(feature = "std")]
+pub use crate::capture::PcapReader;
+#[cfg(feature = "std")]
+pub use crate::capture::PcapWriter;
+
+mod clock;
+
+pub use crate::clock::Clock;
+#[cfg(feature = "std")]
+pub use crate::clock::InstantClock;
+#[cfg(target_has_atomic = "64")]
+pub use crate::clock::VirtualClock;
+
+mod cursor;
+
+pub use crate::cursor::Cursor;
+
+mod metrics;
+
+pub use crate::metrics::render_prometheus;
+pub use crate::metrics::EndpointMetrics;
+#[cfg(feature = "std")]
+pub use crate::metrics::MetricsServer;
+
+mod buffer_pool;
+
+pub use crate::buffer_pool::BufferPool;
+
 mod sequence_buffer;
 
 pub use crate::sequence_buffer::SequenceBuffer;
 
 mod error;
 
+pub use crate::error::ConfigError;
 pub use crate::error::ReliableError;
 
+mod config;
+
+pub use crate::config::AppHeaderSize;
+pub use crate::config::EndpointConfig;
+pub use crate::config::EndpointConfigBuilder;
+
 mod headers;
 
+pub use crate::headers::AckFormat;
+pub use crate::headers::AppHeaderFraming;
+pub use crate::headers::FragmentFormat;
 pub use crate::headers::FragmentHeader;
 pub use crate::headers::HeaderParser as Header;
 pub use crate::headers::PacketHeader;
 
+#[cfg(feature = "rust-backend")]
+mod endpoint;
+
+#[cfg(feature = "rust-backend")]
+pub use crate::endpoint::{replay, Endpoint, ReceivedPacket, ReplayEvent, ReplayReport};
+
 /* TODO:
 enum Counters {
 
@@ -50,467 +108,28 @@ enum Counters {
 
 pub const RELIABLE_MAX_PACKET_HEADER_BYTES: usize = 9;
 pub const RELIABLE_FRAGMENT_HEADER_BYTES: usize = 5;
+pub const RELIABLE_WIDE_FRAGMENT_HEADER_BYTES: usize = 7;
+/// Largest packet header with `AckFormat::Wide`: the 9 byte header plus the extension byte and
+/// four more ack bytes.
+pub const RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES: usize = 14;
 
-#[derive(Clone)]
-pub struct EndpointConfig {
-    pub name: String,
-    pub index: i32,
-    pub max_packet_size: usize,
-    pub fragment_above: usize,
-    pub max_fragments: u32,
-    pub fragment_size: usize,
-    pub ack_buffer_size: usize,
-    pub sent_packets_buffer_size: usize,
-    pub received_packets_buffer_size: usize,
-    pub fragment_reassembly_buffer_size: usize,
-    pub rtt_smoothing_factor: f32,
-    pub packet_loss_smoothing_factor: f32,
-    pub bandwidth_smoothing_factor: f32,
-    pub packet_header_size: usize,
-}
-
-impl EndpointConfig {
-    pub fn new(name: &str) -> Self {
-        let mut r = Self::default();
-        r.name = name.to_string();
-        r
-    }
-}
-
-impl Default for EndpointConfig {
-    fn default() -> Self {
-        Self {
-            name: "".to_string(),
-            index: 1,
-            max_packet_size: 16 * 1024,
-            fragment_above: 1024,
-            max_fragments: 16,
-            fragment_size: 1024,
-            ack_buffer_size: 256,
-            sent_packets_buffer_size: 256,
-            received_packets_buffer_size: 256,
-            fragment_reassembly_buffer_size: 64,
-            rtt_smoothing_factor: 0.0025,
-            packet_loss_smoothing_factor: 0.1,
-            bandwidth_smoothing_factor: 0.1,
-            packet_header_size: 28,
-        }
-    }
-}
-
-/*
-struct reliable_fragment_reassembly_data_t
-{
-    uint16_t sequence;
-    uint16_t ack;
-    uint32_t ack_bits;
-    int num_fragments_received;
-    int num_fragments_total;
-    uint8_t * packet_data;
-    int packet_bytes;
-    int packet_header_bytes;
-    uint8_t fragment_received[256];
-};
-
-*/
-
-#[derive(Clone)]
-struct ReassemblyData {
-    sequence: u16,
-    ack: u16,
-    ack_bits: u32,
-    num_fragments_received: usize,
-    num_fragments_total: usize,
-    buffer: Vec<u8>,
-    fragments_received: [bool; 256],
-    header_size: usize,
-}
-
-impl ReassemblyData {
-    pub fn new(
-        sequence: u16,
-        ack: u16,
-        ack_bits: u32,
-        num_fragments_total: usize,
-        header_size: usize,
-        prealloc: usize,
-    ) -> Self {
-        Self {
-            sequence,
-            ack,
-            ack_bits,
-            num_fragments_received: 0,
-            num_fragments_total,
-            buffer: Vec::with_capacity(prealloc),
-            fragments_received: [false; 256],
-            header_size,
-        }
-    }
-}
-
-impl Default for ReassemblyData {
-    fn default() -> Self {
-        Self {
-            sequence: 0,
-            ack: 0,
-            ack_bits: 0,
-            num_fragments_received: 0,
-            num_fragments_total: 0,
-            buffer: Vec::with_capacity(1024),
-            fragments_received: [false; 256],
-            header_size: 0,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-struct SentData {
-    time: f64,
-    acked: bool,
-    size: usize,
-}
-
-impl SentData {
-    pub fn new(time: f64, size: usize) -> Self {
-        Self {
-            time,
-            size,
-            acked: false,
-        }
-    }
-}
-
-impl Default for SentData {
-    fn default() -> Self {
-        Self {
-            time: 0.0,
-            size: 0,
-            acked: false,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-struct RecvData {
-    time: f64,
-    size: usize,
-}
-
-impl RecvData {
-    pub fn new(time: f64, size: usize) -> Self {
-        Self { time, size }
-    }
-}
-
-impl Default for RecvData {
-    fn default() -> Self {
-        Self { time: 0.0, size: 0 }
-    }
-}
-
-pub struct Endpoint {
-    time: f64,
-    rtt: f32,
-    config: EndpointConfig,
-    acks: Vec<u16>,
-    sequence: i32,
-    sent_buffer: SequenceBuffer<SentData>,
-    recv_buffer: SequenceBuffer<RecvData>,
-    reassembly_buffer: SequenceBuffer<ReassemblyData>,
-    temp_packet_buffer: Vec<u8>,
-}
-
-impl Endpoint {
-    #[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
-    pub fn new(
-        config: EndpointConfig,
-        time: f64
-    ) -> Self {
-        trace!("Creating new endpoint named '{}'", config.name);
-        Self {
-            time,
-            rtt: 0.0,
-            config: config.clone(),
-            acks: Vec::with_capacity(config.ack_buffer_size),
-            sequence: 0,
-            sent_buffer: SequenceBuffer::with_capacity(config.sent_packets_buffer_size),
-            recv_buffer: SequenceBuffer::with_capacity(config.received_packets_buffer_size),
-            reassembly_buffer: SequenceBuffer::with_capacity(
-                config.fragment_reassembly_buffer_size,
-            ),
-            temp_packet_buffer: Vec::with_capacity(config.max_packet_size),
-        }
-    }
-
-    #[cfg_attr(
-    feature = "cargo-clippy",
-    allow(cast_possible_truncation, cast_sign_loss)
-    )]
-    pub fn send(&mut self, packet: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
-        let mut out: Vec<Vec<u8>> = vec![];
-        if packet.len() > self.config.max_packet_size {
-            error!(
-                "Packet too large: Attempting to send {}, max={}",
-                packet.len(),
-                self.config.max_packet_size
-            );
-            return Err(ReliableError::ExceededMaxPacketSize);
-        }
-
-        // Increment sequence
-        let sequence = self.sequence;
-        self.sequence += 1;
-
-        let (ack, ack_bits) = self.recv_buffer.ack_bits();
-
-        let send_size = packet.len() + self.config.packet_header_size;
-        let sent = SentData::new(self.time, send_size);
-        self.sent_buffer.insert(sent, sequence as u16)?;
-
-        let header = PacketHeader::new(sequence as u16, ack, ack_bits);
-
-        if packet.len() <= self.config.fragment_above {
-            // no fragments
-            // TODO: reimplement this as a cursor
-            trace!("Sending packet {} without fragmentation", sequence);
-
-            self.temp_packet_buffer.resize(header.size(), 0);
-            let mut cursor = std::io::Cursor::new(self.temp_packet_buffer.as_mut_slice());
-            header.write(&mut cursor)?;
-            self.temp_packet_buffer.extend_from_slice(packet);
-
-            out.push(self.temp_packet_buffer.clone())
-        } else {
-            let remainder = if packet.len() % self.config.fragment_size > 0 {
-                1
-            } else {
-                0
-            };
-            let num_fragments = (packet.len() / self.config.fragment_size) + remainder;
-
-            trace!(
-                "Sending packet {} with fragmentation, size={}, fragments={}",
-                sequence,
-                packet.len(),
-                num_fragments
-            );
-
-            for fragment_id in 0..num_fragments {
-                let fragment =
-                    FragmentHeader::new(fragment_id as u8, num_fragments as u8, header.clone());
-                self.temp_packet_buffer.resize(fragment.size(), 0);
-
-                let mut cursor = std::io::Cursor::new(self.temp_packet_buffer.as_mut_slice());
-                fragment.write(&mut cursor)?;
-
-                let cur_start = fragment_id * self.config.fragment_size;
-                let mut cur_end = (fragment_id + 1) * self.config.fragment_size;
-                if cur_end > packet.len() {
-                    cur_end = packet.len();
-                }
-
-                self.temp_packet_buffer
-                    .extend_from_slice(&packet[cur_start..cur_end]);
-
-                out.push(self.temp_packet_buffer.clone());
-                self.temp_packet_buffer.clear();
-            }
-        }
-
-        Ok(out)
-    }
-
-    #[cfg_attr(
-    feature = "cargo-clippy",
-    allow(cast_possible_truncation, cast_sign_loss, if_not_else)
-    )]
-    pub fn recv(&mut self, packet: &[u8]) -> Result<Vec<Vec<u8>>, ReliableError> {
-        let mut out: Vec<Vec<u8>> = vec![];
-        if packet.len() > self.config.max_packet_size {
-            error!(
-                "Packet too large: Attempting to recv {}, max={}",
-                packet.len(),
-                self.config.max_packet_size
-            );
-            return Err(ReliableError::ExceededMaxPacketSize);
-        }
-
-        let mut packet_reader = std::io::Cursor::new(packet);
-        let prefix_byte = packet[0];
-
-        if prefix_byte & 1 == 0 {
-            match PacketHeader::parse(&mut packet_reader) {
-                Ok(header) => {
-                    if !self.recv_buffer.check_sequence(header.sequence()) {
-                        error!("Ignoring stale packet: {}", header.sequence());
-                        return Err(ReliableError::StalePacket);
-                    }
-
-
-                    out.push(packet[packet_reader.position() as usize..packet.len()].to_vec());
-
-                    self.recv_buffer.insert(
-                        RecvData::new(self.time, self.config.packet_header_size + packet.len()),
-                        header.sequence(),
-                    )?;
-
-                    let mut ack_bits = header.ack_bits();
-                    for i in 0..32 {
-                        if ack_bits & 1 != 0 {
-                            let ack_sequence: u16 = (Wrapping(header.ack()) - Wrapping(i)).0;
-
-                            if let Some(sent_data) = self.sent_buffer.get_mut(ack_sequence) {
-                                if !sent_data.acked
-                                    && self.acks.len() < self.config.ack_buffer_size
-                                {
-                                    trace!("mark acked packet: {}", ack_sequence);
-                                    self.acks.push(ack_sequence);
-
-                                    sent_data.acked = true;
-                                    let rtt: f32 =
-                                        (self.time as f32 - sent_data.time as f32) * 1000.0;
-                                    if (self.rtt == 0.0 && rtt > 0.0)
-                                        || (self.rtt - rtt).abs() < 0.00001
-                                    {
-                                        self.rtt = rtt;
-                                    } else {
-                                        self.rtt = self.rtt
-                                            + ((rtt - self.rtt)
-                                            * self.config.rtt_smoothing_factor);
-                                    }
-                                }
-                            }
-                        }
-                        ack_bits >>= 1;
-                    }
-
-
-                    return Ok(out);
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            }
-        } else {
-            match FragmentHeader::parse(&mut packet_reader) {
-                Ok(header) => {
-                    trace!(
-                        "parsed fragment header correctly, processing reassembly..: id={}, s={}",
-                        header.sequence(),
-                        header.id()
-                    );
-
-                    {
-                        let reassembly_data =
-                            match self.reassembly_buffer.get_mut(header.sequence()) {
-                                Some(reassembly_data) => reassembly_data,
-                                None => {
-                                    if header.id() == 0 {
-                                        if header.packet_header().is_none() {
-                                            return Err(ReliableError::InvalidFragment);
-                                        }
-
-                                        let ack = header.packet_header().unwrap().ack();
-                                        let ack_bits = header.packet_header().unwrap().ack_bits();
-                                        let reassembly_data = ReassemblyData::new(
-                                            header.sequence(),
-                                            ack,
-                                            ack_bits,
-                                            usize::from(header.count()),
-                                            header.size(),
-                                            RELIABLE_MAX_PACKET_HEADER_BYTES
-                                                + self.config.fragment_size,
-                                        );
-
-                                        self.reassembly_buffer
-                                            .insert(reassembly_data.clone(), header.sequence())?
-                                    } else {
-                                        panic!("Error!");
-                                    }
-                                }
-                            };
-
-                        // Got the data
-                        if reassembly_data.num_fragments_total != usize::from(header.count()) {
-                            return Err(ReliableError::InvalidFragment);
-                        }
-
-                        if reassembly_data.fragments_received[usize::from(header.id())] {
-                            return Err(ReliableError::InvalidFragment);
-                        }
-
-                        reassembly_data.num_fragments_received += 1;
-                        reassembly_data.fragments_received[usize::from(header.id())] = true;
-
-                        trace!(
-                            "{}: recieved fragment #{}/{}, wtf={}",
-                            self.config.name,
-                            header.id() + 1,
-                            header.count(),
-                            reassembly_data.num_fragments_received
-                        );
-
-                        let start_position: usize =
-                            if header.id() == 0 { 5 } else { header.size() };
-
-                        reassembly_data
-                            .buffer
-                            .extend_from_slice(&packet[start_position..packet.len()]);
-
-                        if reassembly_data.num_fragments_received
-                            == reassembly_data.num_fragments_total
-                        {
-                            let sequence = reassembly_data.sequence as u16;
-                            let buffer = reassembly_data.buffer.clone(); // TODO: WHY DO I HAVE TO DO THIS CLONE!?!?!
-
-                            let extra_out = self.recv(buffer.as_slice())?; // oh there's internal nesting...
-                            out.extend(extra_out);
-
-                            self.reassembly_buffer.remove(sequence);
-                        }
-                    }
-                }
-                Err(e) => {
-                    return Err(e);
-                }
-            }
-        }
-
-        return Ok(out);
-    }
-
-    pub fn update(&mut self, time: f64) {
-        self.time = time;
-    }
-
-    pub fn reset(&mut self) {
-        self.sequence = 0;
-
-        self.acks.clear();
-        self.sent_buffer.reset();
-        self.recv_buffer.reset();
-        self.reassembly_buffer.reset();
-    }
-
-    pub fn next_sequence(&self) -> i32 {
-        self.sequence
-    }
-    pub fn acks(&self) -> &[u16] {
-        self.acks.as_slice()
-    }
-}
 
 #[cfg(test)]
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::items_after_statements
+)]
 mod tests {
     const TEST_BUFFER_SIZE: usize = 256;
 
     use super::*;
 
-    use std::sync::{Once, ONCE_INIT};
+    use std::sync::Once;
 
-    static LOGGER_INIT: Once = ONCE_INIT;
+    static LOGGER_INIT: Once = Once::new();
 
-    fn enable_logging() {
+    pub(crate) fn enable_logging() {
         LOGGER_INIT.call_once(|| {
             use env_logger::Builder;
             use log::LevelFilter;
@@ -519,197 +138,6 @@ mod tests {
         });
     }
 
-    fn test_compare<T>(one: &[T], two: &[T]) -> bool
-        where
-            T: PartialEq,
-    {
-        if one.len() != two.len() {
-            return false;
-        }
-        for i in 0..one.len() {
-            if one[i] != two[i] {
-                return false;
-            }
-        }
-        true
-    }
-
-    const TEST_FRAGMENTS_NUM_ITERATIONS: usize = 200;
-
-    #[test]
-    fn fragments() {
-        enable_logging();
-        use std::sync::mpsc::{Receiver, Sender};
-        let (one_send, one_recv): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = std::sync::mpsc::channel();
-        let (two_send, two_recv): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = std::sync::mpsc::channel();
-
-        let def = EndpointConfig::default();
-
-        let mut time = 100.0;
-        let test_data_remainder = [0x41; 4092];
-        let test_data_align = [0x41; 2048];
-
-        let mut test_data = &test_data_align;
-
-        let mut one = Endpoint::new(
-            EndpointConfig::new("one"),
-            time,
-            |_, sequence, buffer| {
-                two_send.send(buffer.to_vec());
-            },
-            |_, _, data| {
-                assert!(test_compare(data, test_data));
-
-                true
-            },
-        );
-
-        let mut two = Endpoint::new(
-            EndpointConfig::new("two"),
-            time,
-            |_, sequence, buffer| {
-                one_send.send(buffer.to_vec());
-            },
-            |_, _, data| {
-                assert!(test_compare(data, test_data));
-
-                true
-            },
-        );
-
-        let delta_time = 0.01;
-        for i in 0..TEST_FRAGMENTS_NUM_ITERATIONS {
-            // forward packets to their endpoints
-            match one_recv.try_recv() {
-                Ok(v) => {
-                    one.recv(v.as_slice());
-                }
-                Err(_) => {}
-            }
-            match two_recv.try_recv() {
-                Ok(v) => {
-                    two.recv(v.as_slice());
-                }
-                Err(_) => {}
-            }
-
-            // Send test packets
-            one.send(test_data);
-            two.send(test_data);
-
-            time += delta_time;
-            one.update(time);
-            two.update(time);
-        }
-
-        let mut test_data = &test_data_remainder;
-
-        let delta_time = 0.01;
-        for i in 0..TEST_FRAGMENTS_NUM_ITERATIONS {
-            // forward packets to their endpoints
-            match one_recv.try_recv() {
-                Ok(v) => {
-                    one.recv(v.as_slice()).unwrap();
-                }
-                Err(_) => {}
-            }
-            match two_recv.try_recv() {
-                Ok(v) => {
-                    two.recv(v.as_slice()).unwrap();
-                }
-                Err(_) => {}
-            }
-
-            // Send test packets
-            one.send(test_data).unwrap();
-            two.send(test_data).unwrap();
-
-            time += delta_time;
-            one.update(time);
-            two.update(time);
-        }
-    }
-
-    const TEST_ACKS_NUM_ITERATIONS: usize = 200;
-
-    #[test]
-    fn acks() {
-        enable_logging();
-        use std::sync::mpsc::{Receiver, Sender};
-        let (one_send, one_recv): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = std::sync::mpsc::channel();
-        let (two_send, two_recv): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = std::sync::mpsc::channel();
-
-        let mut time = 100.0;
-        let test_data = [0x41; 24];
-
-        let mut one = Endpoint::new(
-            EndpointConfig::new("one"),
-            time,
-            |_, sequence, buffer| {
-                trace!("ONE: Sending packet: len={}", buffer.len());
-                two_send.send(buffer.to_vec()).unwrap();
-            },
-            |_, _, data| {
-                assert_eq!(&data, &test_data);
-
-                true
-            },
-        );
-
-        let mut two = Endpoint::new(
-            EndpointConfig::new("two"),
-            time,
-            |_, sequence, buffer| {
-                trace!("TWO: Sending packet: len={}", buffer.len());
-                one_send.send(buffer.to_vec()).unwrap();
-            },
-            |_, _, data| {
-                assert_eq!(&data, &test_data);
-
-                true
-            },
-        );
-
-        let delta_time = 0.01;
-        for i in 0..TEST_ACKS_NUM_ITERATIONS {
-            // forward packets to their endpoints
-            match one_recv.try_recv() {
-                Ok(v) => {
-                    one.recv(v.as_slice()).unwrap();
-                }
-                Err(_) => {}
-            }
-            match two_recv.try_recv() {
-                Ok(v) => {
-                    two.recv(v.as_slice()).unwrap();
-                }
-                Err(_) => {}
-            }
-
-            // Send test packets
-            one.send(&test_data).unwrap();
-            two.send(&test_data).unwrap();
-
-            time += delta_time;
-            one.update(time);
-            two.update(time);
-        }
-
-        /* TODO: I dont understand what he was checking here?
-        let mut one_acked: [u8; TEST_ACKS_NUM_ITERATIONS] = [0; TEST_ACKS_NUM_ITERATIONS];
-        let mut i = 0;
-        for ack in one.acks() {
-            if *ack < TEST_ACKS_NUM_ITERATIONS as u16 {
-                one_acked[*ack as usize] = 1;
-                trace!("Acked: {}", i);
-            }
-            i += 1;
-        }
-        for i in 0..TEST_ACKS_NUM_ITERATIONS / 2 {
-            assert_eq!(one_acked[i], ((i+1) % 2) as u8);
-        }*/
-    }
-
     #[test]
     fn ack_bits() {
         enable_logging();
@@ -721,7 +149,7 @@ mod tests {
 
         let mut buffer = SequenceBuffer::<TestData>::with_capacity(TEST_BUFFER_SIZE);
 
-        for i in 0..TEST_BUFFER_SIZE + 1 {
+        for i in 0..=TEST_BUFFER_SIZE {
             buffer
                 .insert(TestData { sequence: i as u16 }, i as u16)
                 .unwrap();
@@ -730,13 +158,13 @@ mod tests {
         let (ack, ack_bits) = buffer.ack_bits();
 
         assert_eq!(ack, TEST_BUFFER_SIZE as u16);
-        assert_eq!(ack_bits, 0xFFFFFFFF);
+        assert_eq!(ack_bits, 0xFFFF_FFFF);
 
         ////
 
         buffer.reset();
 
-        for ack in [1, 5, 9, 11].iter() {
+        for ack in &[1, 5, 9, 11] {
             buffer
                 .insert(
                     TestData {
@@ -796,7 +224,177 @@ mod tests {
             assert!(entry.is_some());
             let e = entry.unwrap();
             assert_eq!(e.sequence, index as u16);
-            index = index - 1;
+            index -= 1;
+        }
+    }
+
+    #[test]
+    fn sequence_buffer_iter() {
+        let mut buffer = SequenceBuffer::<u16>::with_capacity(16);
+        assert_eq!(buffer.occupied(), 0);
+        assert_eq!(buffer.iter().count(), 0);
+
+        let sequences = [65530, 65531, 65533, 65535, 0, 1, 4];
+        for &sequence in &sequences {
+            buffer.insert(sequence, sequence).unwrap();
+        }
+        assert_eq!(buffer.occupied(), sequences.len());
+        let walked: Vec<(u16, u16)> = buffer.iter().map(|(s, &e)| (s, e)).collect();
+        let expected: Vec<(u16, u16)> = sequences.iter().map(|&s| (s, s)).collect();
+        assert_eq!(walked, expected);
+
+        buffer.retain(|sequence, entry| {
+            *entry = entry.wrapping_add(1);
+            sequence % 2 == 1
+        });
+        assert_eq!(buffer.occupied(), 4);
+        let walked: Vec<(u16, u16)> = buffer.iter().map(|(s, &e)| (s, e)).collect();
+        assert_eq!(
+            walked,
+            vec![(65531, 65532), (65533, 65534), (65535, 0), (1, 2)]
+        );
+        assert!(buffer.get(0).is_none());
+        assert!(buffer.get(4).is_none());
+    }
+
+    const WRAP_TEST_SIZES: [usize; 6] = [3, 7, 32, 100, 256, 1000];
+
+    #[test]
+    fn sequence_buffer_wraparound_inserts() {
+        for &size in &WRAP_TEST_SIZES {
+            let mut buffer = SequenceBuffer::<u16>::with_capacity(size);
+
+            // One full lap of the sequence space plus a few buffers' worth past the wrap.
+            for n in 0..65536 + 3 * size {
+                let sequence = n as u16;
+                buffer.insert(sequence, sequence).unwrap();
+                assert_eq!(buffer.sequence(), sequence.wrapping_add(1));
+                assert_eq!(buffer.get(sequence), Some(&sequence), "size {size}");
+
+                let held = (n + 1).min(size);
+                let oldest = sequence.wrapping_sub(held as u16 - 1);
+                assert_eq!(buffer.get(oldest), Some(&oldest), "size {size} at {sequence}");
+                if n >= size {
+                    let evicted = sequence.wrapping_sub(size as u16);
+                    assert_eq!(buffer.get(evicted), None, "size {size} at {sequence}");
+                }
+
+                let (ack, ack_bits) = buffer.ack_bits();
+                assert_eq!(ack, sequence);
+                let expected_bits = if held >= 32 {
+                    0xFFFF_FFFF
+                } else {
+                    (1u32 << held) - 1
+                };
+                assert_eq!(ack_bits, expected_bits, "size {size} at {sequence}");
+            }
+            assert_eq!(buffer.occupied(), size);
+        }
+    }
+
+    #[test]
+    fn sequence_buffer_wraparound_gaps() {
+        for &size in &WRAP_TEST_SIZES {
+            let mut buffer = SequenceBuffer::<u16>::with_capacity(size);
+
+            for n in (0..65536 + 3 * size).step_by(2) {
+                buffer.insert(n as u16, n as u16).unwrap();
+            }
+            let newest = ((65536 + 3 * size - 1) & !1) as u16;
+            let walked: Vec<u16> = buffer.iter().map(|(sequence, _)| sequence).collect();
+            let expected: Vec<u16> = (0..size.div_ceil(2))
+                .rev()
+                .map(|i| newest.wrapping_sub(2 * i as u16))
+                .collect();
+            assert_eq!(walked, expected, "size {size}");
+
+            let (ack, ack_bits) = buffer.ack_bits();
+            assert_eq!(ack, newest);
+            let window = size.min(32);
+            let mask = if window == 32 {
+                0xFFFF_FFFF
+            } else {
+                (1u32 << window) - 1
+            };
+            assert_eq!(ack_bits, 0x5555_5555 & mask, "size {size}");
+        }
+    }
+
+    #[test]
+    fn sequence_buffer_out_of_order() {
+        for &size in &WRAP_TEST_SIZES {
+            let mut buffer = SequenceBuffer::<u16>::with_capacity(size);
+            let start = 65535u16.wrapping_sub(size as u16 / 2);
+            for i in 0..size as u16 {
+                buffer.insert(0, start.wrapping_add(i)).unwrap();
+            }
+            let next = buffer.sequence();
+
+            // Refilling older slots must not rewind the buffer.
+            for i in (0..size as u16).rev() {
+                let sequence = start.wrapping_add(i);
+                buffer.remove(sequence);
+                assert!(buffer.get(sequence).is_none());
+                buffer.insert(sequence, sequence).unwrap();
+                assert_eq!(buffer.sequence(), next, "size {size}");
+            }
+            for i in 0..size as u16 {
+                let sequence = start.wrapping_add(i);
+                assert_eq!(buffer.get(sequence), Some(&sequence), "size {size}");
+            }
+            assert!(buffer.insert(0, start.wrapping_sub(1)).is_err());
+        }
+    }
+
+    #[test]
+    // Ranges crossing the wrap look reversed to clippy but are how wrapped spans are written.
+    #[allow(clippy::reversed_empty_ranges)]
+    fn sequence_buffer_remove_range() {
+        for &size in &WRAP_TEST_SIZES[1..] {
+            let mut buffer = SequenceBuffer::<u16>::with_capacity(size);
+            for sequence in 65530..=65535 {
+                buffer.insert(sequence, sequence).unwrap();
+            }
+            for sequence in 0..=3 {
+                buffer.insert(sequence, sequence).unwrap();
+            }
+
+            buffer.remove_range(65534..2);
+            for sequence in &[65534, 65535, 0, 1] {
+                assert_eq!(buffer.get(*sequence), None, "size {size}");
+            }
+            assert_eq!(buffer.get(65533), Some(&65533));
+            assert_eq!(buffer.get(2), Some(&2), "end is exclusive");
+            let remaining = size.min(10) - 4;
+            assert_eq!(buffer.occupied(), remaining);
+
+            buffer.remove_range(5..5);
+            assert_eq!(buffer.occupied(), remaining);
+            buffer.remove_range(0..size as u16);
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn sequence_buffer_reset() {
+        for &size in &WRAP_TEST_SIZES {
+            let mut buffer = SequenceBuffer::<u16>::with_capacity(size);
+            assert!(buffer.is_empty());
+            for n in 0..65536 + size {
+                buffer.insert(n as u16, n as u16).unwrap();
+            }
+            assert!(!buffer.is_empty());
+
+            buffer.reset();
+            assert!(buffer.is_empty());
+            assert_eq!(buffer.occupied(), 0);
+            assert_eq!(buffer.sequence(), 0);
+            assert!(buffer.get(0).is_none());
+            assert_eq!(buffer.ack_bits(), (65535, 0));
+
+            buffer.insert(0, 0).unwrap();
+            assert_eq!(buffer.get(0), Some(&0));
+            assert_eq!(buffer.occupied(), 1);
         }
     }
 
@@ -808,13 +406,12 @@ mod tests {
 
         let write_fragment = FragmentHeader::new_fragment(write_id, write_num, write_sequence);
 
-        let mut buffer = vec![];
-        buffer.resize(RELIABLE_MAX_PACKET_HEADER_BYTES, 0);
-        let mut cursor = std::io::Cursor::new(buffer.as_mut_slice());
+        let mut buffer = vec![0; RELIABLE_MAX_PACKET_HEADER_BYTES];
+        let mut cursor = Cursor::new(buffer.as_mut_slice());
 
         write_fragment.write(&mut cursor).unwrap();
 
-        let mut cursor = std::io::Cursor::new(buffer.as_slice());
+        let mut cursor = Cursor::new(buffer.as_slice());
         let read_fragment = FragmentHeader::parse(&mut cursor).unwrap();
 
         assert_eq!(write_fragment.sequence(), read_fragment.sequence());
@@ -822,6 +419,34 @@ mod tests {
         assert_eq!(write_fragment.count(), read_fragment.count());
     }
 
+    #[test]
+    fn wide_fragment_header() {
+        let write_header = PacketHeader::new(999, 990, 0xFFFF_0F0F);
+        let write_fragment = FragmentHeader::new_wide(0, 1000, write_header.clone());
+
+        let mut buffer = vec![0; write_fragment.size()];
+        let mut cursor = Cursor::new(buffer.as_mut_slice());
+        write_fragment.write(&mut cursor).unwrap();
+        assert_eq!(
+            write_fragment.size(),
+            RELIABLE_WIDE_FRAGMENT_HEADER_BYTES + write_header.size()
+        );
+
+        let mut cursor = Cursor::new(buffer.as_slice());
+        let read_fragment = FragmentHeader::parse(&mut cursor).unwrap();
+
+        assert_eq!(read_fragment.format(), FragmentFormat::Wide);
+        assert_eq!(write_fragment, read_fragment);
+
+        let write_fragment = FragmentHeader::new_wide_fragment(777, 1000, 999);
+        let mut buffer = vec![0; write_fragment.size()];
+        let mut cursor = Cursor::new(buffer.as_mut_slice());
+        write_fragment.write(&mut cursor).unwrap();
+
+        let mut cursor = Cursor::new(buffer.as_slice());
+        assert_eq!(write_fragment, FragmentHeader::parse(&mut cursor).unwrap());
+    }
+
     #[test]
     fn packet_header() {
         enable_logging();
@@ -830,14 +455,13 @@ mod tests {
         let write_ack = 100;
         let write_ack_bits = 0;
 
-        let mut buffer = vec![];
-        buffer.resize(RELIABLE_MAX_PACKET_HEADER_BYTES, 0);
-        let mut cursor = std::io::Cursor::new(buffer.as_mut_slice());
+        let mut buffer = vec![0; RELIABLE_MAX_PACKET_HEADER_BYTES];
+        let mut cursor = Cursor::new(buffer.as_mut_slice());
 
         let write_packet = PacketHeader::new(write_sequence, write_ack, write_ack_bits);
         write_packet.write(&mut cursor).unwrap();
 
-        let mut cursor = std::io::Cursor::new(buffer.as_slice());
+        let mut cursor = Cursor::new(buffer.as_slice());
         let read_packet = PacketHeader::parse(&mut cursor).unwrap();
 
         assert_eq!(write_packet.sequence(), read_packet.sequence());
@@ -846,17 +470,277 @@ mod tests {
     }
 
     #[test]
-    fn rust_impl_endpoint() {
+    fn wide_ack_packet_header() {
         enable_logging();
 
-        let _endpoint = Endpoint::new(
-            EndpointConfig::new("balls"),
-            0.0,
-            |_, _, _| trace!("send"),
-            |_, _, _| {
-                trace!("recv");
-                true
-            },
+        let ack_bits_cases: [u64; 4] = [
+            0,
+            0xFFFF_FFFF_FFFF_FFFF,
+            0xFFFF_FFFF_0000_0000,
+            0x12FF_34FF_FF56_FF78,
+        ];
+        for &write_ack_bits in &ack_bits_cases {
+            for &(write_ack, framing) in &[
+                (9950, None),
+                (100, Some(AppHeaderFraming::Fixed)),
+                (9999, Some(AppHeaderFraming::LengthPrefixed)),
+            ] {
+                let mut write_packet = PacketHeader::new_wide(10000, write_ack, write_ack_bits);
+                if let Some(framing) = framing {
+                    write_packet = write_packet.with_app_header(framing);
+                }
+
+                let mut buffer = vec![0; RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES];
+                let mut cursor = Cursor::new(buffer.as_mut_slice());
+                write_packet.write(&mut cursor).unwrap();
+                assert_eq!(cursor.position() as usize, write_packet.size());
+                assert_eq!(buffer[0] & (3 << 6), 1 << 7);
+
+                let mut cursor = Cursor::new(&buffer[..write_packet.size()]);
+                let read_packet = PacketHeader::parse(&mut cursor).unwrap();
+                assert_eq!(read_packet, write_packet);
+                assert_eq!(read_packet.ack_format(), AckFormat::Wide);
+                assert_eq!(read_packet.wide_ack_bits(), write_ack_bits);
+                assert_eq!(u64::from(read_packet.ack_bits()), write_ack_bits & 0xFFFF_FFFF);
+            }
+        }
+
+        // Fully set upper ack bytes cost only the extension byte.
+        let narrow = PacketHeader::new(10, 9, 0x1234_5678);
+        let wide = PacketHeader::new_wide(10, 9, 0xFFFF_FFFF_1234_5678);
+        assert_eq!(wide.size(), narrow.size() + 1);
+
+        // Reserved extension bits are rejected.
+        let mut buffer = vec![0; RELIABLE_MAX_WIDE_ACK_PACKET_HEADER_BYTES];
+        wide.write(&mut Cursor::new(buffer.as_mut_slice())).unwrap();
+        buffer[1] |= 1 << 7;
+        assert!(PacketHeader::parse(&mut Cursor::new(buffer.as_slice())).is_err());
+    }
+
+    #[test]
+    fn config_builder() {
+        let config = EndpointConfig::builder("built")
+            .max_packet_size(64 * 1024)
+            .fragment_size(512)
+            .max_fragments(128)
+            .build()
+            .unwrap();
+        assert_eq!(config.name, "built");
+        assert_eq!(config.max_packet_size, 64 * 1024);
+        #[cfg(feature = "rust-backend")]
+        assert!(Endpoint::new(config, 0.0).is_ok());
+
+        assert_eq!(
+            EndpointConfig::builder("big fragments")
+                .max_packet_size(1024)
+                .fragment_size(2048)
+                .build()
+                .err(),
+            Some(ConfigError::FragmentSizeExceedsMaxPacketSize {
+                fragment_size: 2048,
+                max_packet_size: 1024,
+            })
+        );
+        assert_eq!(
+            EndpointConfig::builder("few fragments")
+                .max_fragments(8)
+                .build()
+                .err(),
+            Some(ConfigError::TooFewFragments {
+                max_fragments: 8,
+                fragment_size: 1024,
+                max_packet_size: 16 * 1024,
+            })
+        );
+        assert_eq!(
+            EndpointConfig::builder("narrow")
+                .max_fragments(300)
+                .build()
+                .err(),
+            Some(ConfigError::TooManyFragmentsForFormat {
+                max_fragments: 300,
+                format: FragmentFormat::Narrow,
+            })
+        );
+        assert_eq!(
+            EndpointConfig::builder("no buffer")
+                .received_packets_buffer_size(0)
+                .build()
+                .err(),
+            Some(ConfigError::ZeroSize("received_packets_buffer_size"))
+        );
+        assert_eq!(
+            EndpointConfig::builder("no acks")
+                .ack_buffer_size(0)
+                .build()
+                .err(),
+            Some(ConfigError::ZeroSize("ack_buffer_size"))
+        );
+        assert_eq!(
+            EndpointConfig::builder("smoothing")
+                .rtt_smoothing_factor(1.5)
+                .build()
+                .err(),
+            Some(ConfigError::SmoothingFactorOutOfRange("rtt_smoothing_factor", 1.5))
+        );
+
+        let mut config = EndpointConfig::new("by hand");
+        config.packet_loss_smoothing_factor = f32::NAN;
+        assert!(config.validate().is_err());
+        #[cfg(feature = "rust-backend")]
+        assert!(Endpoint::new(config, 0.0).is_err());
+
+        assert_eq!(
+            ReliableError::from(ConfigError::ZeroSize("ack_buffer_size")).to_string(),
+            "invalid config: ack_buffer_size must be greater than zero"
+        );
+        assert_eq!(ReliableError::StalePacket.to_string(), "stale packet");
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn config_from_toml() {
+        let config = EndpointConfig::from_toml_str(
+            r#"
+            name = "server"
+            max_packet_size = 32768
+            max_fragments = 32
+            fragment_format = "wide"
+            app_header = { fixed = 4 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.name, "server");
+        assert_eq!(config.max_packet_size, 32768);
+        assert_eq!(config.max_fragments, 32);
+        assert_eq!(config.fragment_format, FragmentFormat::Wide);
+        assert_eq!(config.app_header, Some(AppHeaderSize::Fixed(4)));
+        assert_eq!(config.fragment_size, EndpointConfig::default().fragment_size);
+
+        match EndpointConfig::from_toml_str("max_fragments = 4") {
+            Err(ConfigError::TooFewFragments { .. }) => {}
+            _ => panic!("loaded configs must be validated"),
+        }
+        match EndpointConfig::from_toml_str("max_fragments = \"many\"") {
+            Err(ConfigError::Parse(_)) => {}
+            _ => panic!("malformed configs must fail to parse"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn config_from_json() {
+        let config =
+            EndpointConfig::from_json_str(r#"{ "name": "client", "app_header": "variable" }"#)
+                .unwrap();
+        assert_eq!(config.name, "client");
+        assert_eq!(config.app_header, Some(AppHeaderSize::Variable));
+        assert_eq!(config.max_packet_size, EndpointConfig::default().max_packet_size);
+
+        let path = std::env::temp_dir().join("reliable_config_from_json.json");
+        std::fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+        let loaded = EndpointConfig::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.name, "client");
+        assert_eq!(loaded.app_header, Some(AppHeaderSize::Variable));
+    }
+
+    /// Runs the same exchange over either backend through `ReliableEndpoint`.
+    fn exchange_through_trait<E: ReliableEndpoint>(mut one: E, mut two: E) {
+        let mut time = 100.0;
+        for i in 0..200 {
+            // Every fifth packet is split into three fragments.
+            let packet = vec![i as u8; if i % 5 == 0 { 3000 } else { 100 }];
+            for datagram in one.send(&packet).unwrap() {
+                for received in two.receive(&datagram).unwrap() {
+                    assert_eq!(received, packet);
+                }
+            }
+
+            time += 0.01;
+            one.update(time);
+            two.update(time);
+            for datagram in two.send(&[i as u8; 10]).unwrap() {
+                assert_eq!(one.receive(&datagram).unwrap(), vec![vec![i as u8; 10]]);
+            }
+            assert_eq!(one.acks(), vec![i]);
+            one.clear_acks();
+        }
+
+        assert!(one.send(&vec![0; 17 * 1024]).is_err());
+        assert!(matches!(one.send(&[]), Err(ReliableError::PacketTooSmall)));
+        assert!(one.receive(&[0xFF; 2]).is_err());
+
+        let counters = one.counters();
+        assert_eq!(counters.packets_sent, 200);
+        assert_eq!(counters.fragments_sent, 120);
+        assert_eq!(counters.packets_acked, 200);
+        assert_eq!(counters.packets_too_large_to_send, 1);
+        assert_eq!(counters.fragments_invalid, 1);
+        assert_eq!(two.counters().packets_received, 200);
+        assert_eq!(two.counters().fragments_received, 120);
+
+        // Replies arrive one 10ms update after each packet was sent.
+        assert!((one.rtt() - 10.0).abs() < 0.01);
+        assert!(one.packet_loss() < 1.0);
+        let (sent, received, acked) = one.bandwidth();
+        assert!(sent > 0.0 && received > 0.0 && acked > 0.0);
+
+        one.reset();
+        assert!(one.acks().is_empty());
+        assert_eq!(one.counters(), counters);
+    }
+
+    /// Sends one packet each way per update until past the 16-bit wrap, checking the acks keep
+    /// counting up.
+    fn acks_past_wrap_through_trait<E: ReliableEndpoint>(mut one: E, mut two: E) {
+        let mut time = 100.0;
+        for i in 0..66_000u32 {
+            for datagram in one.send(&[1; 8]).unwrap() {
+                two.receive(&datagram).unwrap();
+            }
+            for datagram in two.send(&[2; 8]).unwrap() {
+                one.receive(&datagram).unwrap();
+            }
+            assert_eq!(one.acks(), vec![i]);
+            one.clear_acks();
+
+            time += 0.001;
+            one.update(time);
+            two.update(time);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rust-backend")]
+    fn rust_reliable_endpoint() {
+        enable_logging();
+
+        exchange_through_trait(
+            Endpoint::new(EndpointConfig::new("one"), 100.0).unwrap(),
+            Endpoint::new(EndpointConfig::new("two"), 100.0).unwrap(),
+        );
+        acks_past_wrap_through_trait(
+            Endpoint::new(EndpointConfig::new("one"), 100.0).unwrap(),
+            Endpoint::new(EndpointConfig::new("two"), 100.0).unwrap(),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "c-backend")]
+    fn c_reliable_endpoint() {
+        use crate::binding_version::{BufferedEndpoint, Config};
+
+        fn assert_send<T: Send>() {}
+        assert_send::<BufferedEndpoint>();
+
+        exchange_through_trait(
+            BufferedEndpoint::new(Config::default()).unwrap(),
+            BufferedEndpoint::new(Config::default()).unwrap(),
+        );
+        acks_past_wrap_through_trait(
+            BufferedEndpoint::new(Config::default()).unwrap(),
+            BufferedEndpoint::new(Config::default()).unwrap(),
         );
     }
 }
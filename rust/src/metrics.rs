@@ -0,0 +1,275 @@
+//! Endpoint stats in the Prometheus text exposition format, and a minimal HTTP listener on
+//! localhost to scrape them from.
+//!
+//! Every metric is labelled with the endpoint's name, so the stats of many endpoints render
+//! into one scrape.
+
+use crate::{Counters, ReliableEndpoint};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// A snapshot of the stats of one endpoint, see `render_prometheus`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointMetrics {
+    pub name: String,
+    pub rtt: f32,
+    pub packet_loss: f32,
+    /// Sent, received and acked bandwidth in kbps.
+    pub bandwidth: (f32, f32, f32),
+    pub counters: Counters,
+}
+
+impl EndpointMetrics {
+    /// Takes the stats of an endpoint of either backend, labelled `name`.
+    pub fn new<E: ReliableEndpoint + ?Sized>(name: &str, endpoint: &E) -> Self {
+        Self {
+            name: name.into(),
+            rtt: endpoint.rtt(),
+            packet_loss: endpoint.packet_loss(),
+            bandwidth: endpoint.bandwidth(),
+            counters: endpoint.counters(),
+        }
+    }
+}
+
+/// Renders the stats of `endpoints` as Prometheus gauges and counters.
+pub fn render_prometheus(endpoints: &[EndpointMetrics]) -> String {
+    let mut out = String::new();
+
+    gauge(
+        &mut out,
+        "reliable_rtt_milliseconds",
+        "Smoothed round trip time.",
+    );
+    for endpoint in endpoints {
+        sample(
+            &mut out,
+            "reliable_rtt_milliseconds",
+            endpoint,
+            None,
+            endpoint.rtt,
+        );
+    }
+
+    gauge(
+        &mut out,
+        "reliable_packet_loss_percent",
+        "Smoothed percentage of sent packets that were not acked.",
+    );
+    for endpoint in endpoints {
+        let loss = endpoint.packet_loss;
+        sample(
+            &mut out,
+            "reliable_packet_loss_percent",
+            endpoint,
+            None,
+            loss,
+        );
+    }
+
+    gauge(&mut out, "reliable_bandwidth_kbps", "Smoothed bandwidth.");
+    for endpoint in endpoints {
+        let (sent, received, acked) = endpoint.bandwidth;
+        for (direction, kbps) in [("sent", sent), ("received", received), ("acked", acked)] {
+            let label = Some(("direction", direction));
+            sample(&mut out, "reliable_bandwidth_kbps", endpoint, label, kbps);
+        }
+    }
+
+    let values: Vec<[u64; 10]> = endpoints
+        .iter()
+        .map(|endpoint| counter_values(&endpoint.counters))
+        .collect();
+    for (index, (counter, help)) in COUNTERS.iter().enumerate() {
+        let name = alloc::format!("reliable_{counter}_total");
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for (endpoint, values) in endpoints.iter().zip(&values) {
+            let _ = writeln!(
+                out,
+                "{}{{endpoint=\"{}\"}} {}",
+                name,
+                escape(&endpoint.name),
+                values[index]
+            );
+        }
+    }
+
+    out
+}
+
+/// Name and help text of each counter, in the order `counter_values` returns them.
+const COUNTERS: [(&str, &str); 10] = [
+    ("packets_sent", "Packets sent."),
+    ("packets_received", "Packets received."),
+    ("packets_acked", "Sent packets acked by the peer."),
+    ("packets_stale", "Received packets dropped as stale."),
+    ("packets_invalid", "Received packets that failed to parse."),
+    ("packets_too_large_to_send", "Packets too large to send."),
+    (
+        "packets_too_large_to_receive",
+        "Datagrams too large to receive.",
+    ),
+    ("fragments_sent", "Fragments sent."),
+    ("fragments_received", "Fragments received."),
+    ("fragments_invalid", "Received fragments that were refused."),
+];
+
+fn counter_values(counters: &Counters) -> [u64; 10] {
+    [
+        counters.packets_sent,
+        counters.packets_received,
+        counters.packets_acked,
+        counters.packets_stale,
+        counters.packets_invalid,
+        counters.packets_too_large_to_send,
+        counters.packets_too_large_to_receive,
+        counters.fragments_sent,
+        counters.fragments_received,
+        counters.fragments_invalid,
+    ]
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} gauge");
+}
+
+fn sample(
+    out: &mut String,
+    name: &str,
+    endpoint: &EndpointMetrics,
+    label: Option<(&str, &str)>,
+    value: f32,
+) {
+    let _ = write!(out, "{}{{endpoint=\"{}\"", name, escape(&endpoint.name));
+    if let Some((label, label_value)) = label {
+        let _ = write!(out, ",{label}=\"{label_value}\"");
+    }
+    let _ = writeln!(out, "}} {value}");
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(feature = "std")]
+pub use self::server::MetricsServer;
+
+#[cfg(feature = "std")]
+mod server {
+    use log::*;
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    /// How long a connection may stall reading the request or writing the response. Clients are
+    /// served one at a time, so this bounds how long an idle one holds up the rest.
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Requests are only read to their blank line; anything longer than this is dropped.
+    const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+    /// Serves `render_prometheus` output over HTTP on localhost from a background thread.
+    ///
+    /// Every request is answered with the metrics, whatever its path. Connections are served one
+    /// at a time and dropped if they stall for a second. The thread stops when the server is
+    /// dropped.
+    pub struct MetricsServer {
+        address: SocketAddr,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl MetricsServer {
+        /// Listens on 127.0.0.1:`port`, or a free port if `port` is 0, answering each scrape
+        /// with the text `render` returns.
+        pub fn bind<F>(port: u16, render: F) -> io::Result<Self>
+        where
+            F: Fn() -> String + Send + 'static,
+        {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+            let address = listener.local_addr()?;
+            let stop = Arc::new(AtomicBool::new(false));
+
+            let stopping = stop.clone();
+            let thread = thread::Builder::new()
+                .name("reliable-metrics".into())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        if stopping.load(Ordering::Acquire) {
+                            break;
+                        }
+                        let result = stream.and_then(|stream| respond(stream, &render()));
+                        if let Err(e) = result {
+                            warn!("Failed to serve metrics: {e}");
+                        }
+                    }
+                })?;
+
+            Ok(Self {
+                address,
+                stop,
+                thread: Some(thread),
+            })
+        }
+
+        pub fn local_addr(&self) -> SocketAddr {
+            self.address
+        }
+    }
+
+    impl Drop for MetricsServer {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Release);
+            // Wake the listener so it sees the stop flag.
+            let _ = TcpStream::connect(self.address);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    fn respond(mut stream: TcpStream, body: &str) -> io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut reader = BufReader::new((&stream).take(MAX_REQUEST_SIZE));
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+            line.clear();
+        }
+        if reader.get_ref().limit() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("request longer than {MAX_REQUEST_SIZE} bytes"),
+            ));
+        }
+        drop(reader);
+
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+}
@@ -1,30 +1,40 @@
 use crate::ReliableError;
-use std::num::Wrapping;
+use alloc::vec::Vec;
+use core::num::Wrapping;
+
+const EMPTY: u32 = 0xFFFF_FFFF;
 
 pub struct SequenceBuffer<T>
 where
-    T: Default + std::clone::Clone + Send + Sync,
+    T: Default + core::clone::Clone + Send + Sync,
 {
     entries: Vec<T>,
     entry_sequences: Vec<u32>,
     sequence: u16,
+    /// Number of times `sequence` has wrapped past 65535, used to give every sequence a stable
+    /// slot when `size` does not divide 65536.
+    wraps: i64,
     size: usize,
+    /// `size - 1` when `size` is a power of two that divides 65536, so `index` can mask.
+    mask: Option<usize>,
 }
 
 impl<T> SequenceBuffer<T>
 where
-    T: Default + std::clone::Clone + Send + Sync,
+    T: Default + core::clone::Clone + Send + Sync,
 {
     pub fn with_capacity(size: usize) -> Self {
         let mut entries = Vec::with_capacity(size);
         let mut entry_sequences = Vec::with_capacity(size);
 
         entries.resize(size, T::default());
-        entry_sequences.resize(size, 0xFFFF_FFFF);
+        entry_sequences.resize(size, EMPTY);
 
         Self {
             sequence: 0,
+            wraps: 0,
             size,
+            mask: (size.is_power_of_two() && size <= 65536).then(|| size - 1),
             entries,
             entry_sequences,
         }
@@ -48,80 +58,185 @@ where
         Some(&mut self.entries[index])
     }
 
-    #[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation))]
     pub fn insert(&mut self, data: T, sequence: u16) -> Result<&mut T, ReliableError> {
+        self.insert_with(data, sequence, drop)
+    }
+
+    /// Like `insert`, but hands every entry it pushes out of the buffer to `evicted` instead of
+    /// dropping it, so entries holding pooled buffers can give them back.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn insert_with<F>(
+        &mut self,
+        data: T,
+        sequence: u16,
+        mut evicted: F,
+    ) -> Result<&mut T, ReliableError>
+    where
+        F: FnMut(T),
+    {
         if Self::sequence_less_than(
             sequence,
             (Wrapping(self.sequence) - Wrapping(self.len() as u16)).0,
         ) {
             return Err(ReliableError::SequenceBufferFull);
         }
-        if Self::sequence_greater_than((Wrapping(sequence) + Wrapping(1)).0, self.sequence) {
-            self.remove_range(self.sequence..sequence);
+        let index = self.index(sequence);
 
-            self.sequence = (Wrapping(sequence) + Wrapping(1)).0;
-        }
+        let next = (Wrapping(sequence) + Wrapping(1)).0;
+        if Self::sequence_greater_than(next, self.sequence) {
+            self.evict_range(self.sequence..sequence, &mut evicted);
 
-        let index = self.index(sequence);
+            if next < self.sequence {
+                self.wraps += 1;
+            }
+            self.sequence = next;
+        }
 
-        self.entries[index] = data;
+        evicted(core::mem::replace(&mut self.entries[index], data));
         self.entry_sequences[index] = u32::from(sequence);
 
-        self.sequence = (Wrapping(sequence) + Wrapping(1)).0;
-
         Ok(&mut self.entries[index])
     }
 
-    // TODO: THIS IS INCLUSIVE END
-    pub fn remove_range(&mut self, range: std::ops::Range<u16>) {
-        for i in range.clone() {
-            self.remove(i);
+    /// Removes every sequence in `range`, end exclusive. The range may wrap past 65535, e.g.
+    /// `65530..2` covers 65530 to 1.
+    pub fn remove_range(&mut self, range: core::ops::Range<u16>) {
+        self.evict_range(range, &mut drop);
+    }
+
+    fn evict_range<F>(&mut self, range: core::ops::Range<u16>, evicted: &mut F)
+    where
+        F: FnMut(T),
+    {
+        let count = usize::from((Wrapping(range.end) - Wrapping(range.start)).0);
+        if count >= self.len() {
+            for index in 0..self.len() {
+                evicted(self.clear(index));
+            }
+            return;
+        }
+
+        // Slots are cleared whether or not they hold the sequence itself, so entries that fell
+        // out of the window behind these sequences go too.
+        let mut sequence = Wrapping(range.start);
+        for _ in 0..count {
+            let index = self.index(sequence.0);
+            evicted(self.clear(index));
+            sequence += Wrapping(1);
         }
-        self.remove(range.end);
     }
 
+    /// Removes `sequence` if it is present.
     pub fn remove(&mut self, sequence: u16) {
-        // TODO: validity check
         let index = self.index(sequence);
-        self.entries[index] = T::default();
-        self.entry_sequences[index] = 0xFFFF_FFFF;
+        if self.entry_sequences[index] == u32::from(sequence) {
+            self.clear(index);
+        }
+    }
+
+    fn clear(&mut self, index: usize) -> T {
+        self.entry_sequences[index] = EMPTY;
+        core::mem::take(&mut self.entries[index])
     }
 
     pub fn reset(&mut self) {
         self.sequence = 0;
+        self.wraps = 0;
         for e in &mut self.entry_sequences {
-            *e = 0;
+            *e = EMPTY;
         }
     }
 
+    /// Like `reset`, but hands every entry still in the buffer to `evicted` first, so entries
+    /// holding pooled buffers can give them back.
+    pub fn reset_with<F>(&mut self, mut evicted: F)
+    where
+        F: FnMut(T),
+    {
+        for index in 0..self.len() {
+            if self.entry_sequences[index] != EMPTY {
+                evicted(self.clear(index));
+            }
+        }
+        self.reset();
+    }
+
     pub fn sequence(&self) -> u16 {
         self.sequence
     }
 
+    /// Empties the buffer and moves its window to end at `sequence`, `wraps` laps in, used when
+    /// restoring snapshots before re-inserting their entries.
+    pub(crate) fn reset_to(&mut self, sequence: u16, wraps: i64) {
+        self.reset();
+        self.sequence = sequence;
+        self.wraps = wraps;
+    }
+
+    /// Number of times `sequence()` has wrapped past 65535.
+    pub(crate) fn wraps(&self) -> i64 {
+        self.wraps
+    }
+
+    /// Number of slots, occupied or not.
     pub fn len(&self) -> usize {
         self.entries.len()
     }
 
+    /// Whether no slot currently holds an entry.
     pub fn is_empty(&self) -> bool {
-        self.len() > 0
+        self.occupied() == 0
     }
 
     pub fn capacity(&self) -> usize {
         self.entries.capacity()
     }
 
+    /// Number of slots currently holding an entry.
+    pub fn occupied(&self) -> usize {
+        self.entry_sequences
+            .iter()
+            .filter(|&&s| s != EMPTY)
+            .count()
+    }
+
+    /// Iterates the occupied `(sequence, entry)` pairs in the window ending at `sequence()`,
+    /// oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &T)> + '_ {
+        self.window()
+            .filter_map(move |sequence| self.get(sequence).map(|entry| (sequence, entry)))
+    }
+
+    /// Keeps only the entries for which `f` returns `true`, visiting them oldest first.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(u16, &mut T) -> bool,
+    {
+        for sequence in self.window() {
+            if let Some(entry) = self.get_mut(sequence) {
+                if !f(sequence, entry) {
+                    self.remove(sequence);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn window(&self) -> impl Iterator<Item = u16> {
+        let len = self.len() as u16;
+        let oldest = (Wrapping(self.sequence) - Wrapping(len)).0;
+        (0..len).map(move |i| (Wrapping(oldest) + Wrapping(i)).0)
+    }
+
     #[allow(unused)]
-    #[cfg_attr(
-        feature = "cargo-clippy",
-        allow(cast_possible_truncation, cast_sign_loss)
-    )]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
     pub fn ack_bits(&self) -> (u16, u32) {
-        let ack = (Wrapping(self.sequence as u16) - Wrapping(1)).0;
+        let ack = (Wrapping(self.sequence) - Wrapping(1)).0;
         let mut ack_bits: u32 = 0;
         let mut mask: u32 = 1;
 
-        for i in 0..33 {
-            let sequence = (Wrapping(ack) - Wrapping(i as u16)).0 as u16;
+        for i in 0..32 {
+            let sequence = (Wrapping(ack) - Wrapping(i as u16)).0;
 
             if let Some(s) = self.get(sequence) {
                 ack_bits |= mask;
@@ -132,23 +247,51 @@ where
         (ack, ack_bits)
     }
 
+    /// Like `ack_bits`, but covering the 64 sequences up to and including the ack.
+    pub fn wide_ack_bits(&self) -> (u16, u64) {
+        let ack = (Wrapping(self.sequence) - Wrapping(1)).0;
+        let mut ack_bits: u64 = 0;
+
+        for i in 0..64 {
+            if self.get((Wrapping(ack) - Wrapping(i)).0).is_some() {
+                ack_bits |= 1 << i;
+            }
+        }
+        (ack, ack_bits)
+    }
+
+    /// Slot for `sequence`, taken from its position relative to `sequence()` with wraps
+    /// unrolled so that sizes which don't divide 65536 map consistently across 65535 -> 0.
     #[inline]
-    #[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation))]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )]
     fn index(&self, sequence: u16) -> usize {
-        (sequence % self.entries.len() as u16) as usize
+        if let Some(mask) = self.mask {
+            return usize::from(sequence) & mask;
+        }
+        let next = (self.wraps << 16) + i64::from(self.sequence);
+        let unwrapped = if Self::sequence_less_than(sequence, self.sequence) {
+            next - i64::from((Wrapping(self.sequence) - Wrapping(sequence)).0)
+        } else {
+            next + i64::from((Wrapping(sequence) - Wrapping(self.sequence)).0)
+        };
+        unwrapped.rem_euclid(self.entries.len() as i64) as usize
     }
 
     #[inline]
     pub fn sequence_greater_than(s1: u16, s2: u16) -> bool {
-        ((s1 > s2) && (s1 - s2 <= 32768)) || ((s1 < s2) && (s2 - s1 > 32768))
+        sequence_greater_than(s1, s2)
     }
     #[inline]
     pub fn sequence_less_than(s1: u16, s2: u16) -> bool {
-        Self::sequence_greater_than(s2, s1)
+        sequence_less_than(s1, s2)
     }
 
     #[inline]
-    #[cfg_attr(feature = "cargo-clippy", allow(cast_possible_truncation))]
+    #[allow(clippy::cast_possible_truncation)]
     pub fn check_sequence(&self, sequence: u16) -> bool {
         Self::sequence_greater_than(
             sequence,
@@ -156,3 +299,25 @@ where
         )
     }
 }
+
+#[inline]
+pub(crate) fn sequence_greater_than(s1: u16, s2: u16) -> bool {
+    ((s1 > s2) && (s1 - s2 <= 32768)) || ((s1 < s2) && (s2 - s1 > 32768))
+}
+
+#[inline]
+fn sequence_less_than(s1: u16, s2: u16) -> bool {
+    sequence_greater_than(s2, s1)
+}
+
+/// Extends `sequence` to the 32-bit sequence nearest to `reference`, within half the 16-bit
+/// sequence space either side of it.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn extend_sequence(reference: u32, sequence: u16) -> u32 {
+    let low = reference as u16;
+    if sequence_greater_than(sequence, low) {
+        reference.wrapping_add(u32::from(sequence.wrapping_sub(low)))
+    } else {
+        reference.wrapping_sub(u32::from(low.wrapping_sub(sequence)))
+    }
+}